@@ -0,0 +1,291 @@
+use flate2::{Compression, GzBuilder};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{Context, Result, bail};
+use crate::node::{Node, NodeKind};
+
+// 1980-01-01, the earliest time a zip file can hold
+const DEFAULT_MTIME: u64 = 315_532_800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    // picked by extension: .tar, .tar.gz or .tgz, .zip
+    pub fn from_path(path: &Path) -> Option<ArchiveFormat> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+// One archive entry: its name with '/' separators, no trailing '/' for directories
+struct Entry<'a> {
+    name: String,
+    node: &'a Node,
+}
+
+// Writes collected entries (see plan::collect_groups) to an archive instead of
+// the disk. Names are relative to `base`. The output is reproducible: entries in
+// path order, every timestamp set to $SOURCE_DATE_EPOCH (1980-01-01 without it),
+// owner root, mode 755 for directories and scripts starting with "#!", 644 otherwise.
+// The archive is written next to `path` under a temporary name and renamed into
+// place, so a failed write leaves whatever was at `path` untouched. Whether an
+// existing file may be replaced is up to the caller.
+pub fn write_archive(path: &Path, base: &Path, all_paths: &BTreeMap<PathBuf, Node>) -> Result<()> {
+    let Some(format) = ArchiveFormat::from_path(path) else {
+        bail!("Unknown archive type '{}', use .tar, .tar.gz, .tgz or .zip", path.display());
+    };
+    if path.is_dir() {
+        bail!("'{}' is a directory", path.display());
+    }
+    let entries = archive_entries(base, all_paths)?;
+    let mtime = source_date_epoch()?;
+
+    let temp = temp_path(path);
+    let file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .with_context(|| format!("Failed to create '{}'", temp.display()))?;
+    let written = match format {
+        ArchiveFormat::Tar => write_tar(file, &entries, mtime).map(drop),
+        ArchiveFormat::TarGz => write_tar(GzBuilder::new().write(file, Compression::default()), &entries, mtime)
+            .and_then(|gz| gz.finish())
+            .map(drop),
+        ArchiveFormat::Zip => write_zip(file, &entries, mtime),
+    };
+    let done = written.and_then(|()| fs::rename(&temp, path));
+    if let Err(e) = done {
+        // don't leave half an archive behind
+        let _ = fs::remove_file(&temp);
+        return Err(e).with_context(|| format!("Failed to write '{}'", path.display()));
+    }
+    Ok(())
+}
+
+// ".<name>.treegen-tmp<n>" in the directory of `path`, not taken yet
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut n = std::process::id();
+    loop {
+        let candidate = path.with_file_name(format!(".{}.treegen-tmp{}", name, n));
+        if !candidate.exists() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+fn archive_entries<'a>(base: &Path, all_paths: &'a BTreeMap<PathBuf, Node>) -> Result<Vec<Entry<'a>>> {
+    let mut entries = Vec::new();
+    for (path, node) in all_paths {
+        let rel = path.strip_prefix(base).unwrap_or(path);
+        let mut parts = Vec::new();
+        for component in rel.components() {
+            match component {
+                Component::Normal(part) => parts.push(part.to_string_lossy()),
+                Component::CurDir => {}
+                _ => bail!("'{}' can't be stored in an archive", path.display()),
+            }
+        }
+        if !parts.is_empty() {
+            entries.push(Entry { name: parts.join("/"), node });
+        }
+    }
+    Ok(entries)
+}
+
+fn mode(node: &Node) -> u32 {
+    let script = node.content.as_deref().is_some_and(|c| c.starts_with(b"#!"));
+    if node.kind == NodeKind::Dir || script { 0o755 } else { 0o644 }
+}
+
+// $SOURCE_DATE_EPOCH, the reproducible-builds convention, or 1980-01-01
+fn source_date_epoch() -> Result<u64> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) if !value.trim().is_empty() => match value.trim().parse() {
+            Ok(secs) => Ok(secs),
+            Err(_) => bail!("SOURCE_DATE_EPOCH must be a number of seconds, got '{}'", value),
+        },
+        _ => Ok(DEFAULT_MTIME),
+    }
+}
+
+fn write_tar<W: Write>(out: W, entries: &[Entry], mtime: u64) -> io::Result<W> {
+    let mut builder = tar::Builder::new(out);
+    for entry in entries {
+        let content = entry.node.content.as_deref().unwrap_or_default();
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(mode(entry.node));
+        header.set_mtime(mtime);
+        header.set_uid(0);
+        header.set_gid(0);
+        match entry.node.kind {
+            NodeKind::Dir => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_size(0);
+                builder.append_data(&mut header, format!("{}/", entry.name), io::empty())?;
+            }
+            NodeKind::File => {
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(content.len() as u64);
+                builder.append_data(&mut header, &entry.name, content)?;
+            }
+        }
+    }
+    builder.into_inner()
+}
+
+fn write_zip(out: File, entries: &[Entry], mtime: u64) -> io::Result<()> {
+    let mut zip = zip::ZipWriter::new(out);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip_time(mtime));
+    for entry in entries {
+        let options = options.unix_permissions(mode(entry.node));
+        match entry.node.kind {
+            NodeKind::Dir => zip.add_directory(format!("{}/", entry.name), options)?,
+            NodeKind::File => {
+                zip.start_file(entry.name.as_str(), options)?;
+                zip.write_all(entry.node.content.as_deref().unwrap_or_default())?;
+            }
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+// Zip stores local calendar time from 1980 to 2107, taken here as UTC
+fn zip_time(secs: u64) -> zip::DateTime {
+    let days = (secs / 86_400) as i64;
+    let rest = secs % 86_400;
+    // days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u8;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    if year < 1980 {
+        return zip::DateTime::default();
+    }
+    let year = year.min(2107) as u16;
+    let (hour, minute, second) = ((rest / 3600) as u8, (rest / 60 % 60) as u8, (rest % 60) as u8);
+    zip::DateTime::from_date_and_time(year, month, day, hour, minute, second).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn entries() -> BTreeMap<PathBuf, Node> {
+        let nodes = [
+            Node::dir("out/bin"),
+            Node { content: Some(b"#!/bin/sh\necho hi\n".to_vec()), ..Node::file("out/bin/run.sh") },
+            Node { content: Some(b"# demo\n".to_vec()), ..Node::file("out/README.md") },
+            Node::file("out/empty.txt"),
+        ];
+        nodes.into_iter().map(|n| (n.path.clone(), n)).collect()
+    }
+
+    const MODES: [(&str, u32); 4] = [("README.md", 0o644), ("bin", 0o755), ("bin/run.sh", 0o755), ("empty.txt", 0o644)];
+
+    fn tar_modes(reader: impl Read) -> Vec<(String, u32)> {
+        let mut archive = tar::Archive::new(reader);
+        let mut modes: Vec<(String, u32)> = archive
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                let name = e.path().unwrap().to_string_lossy().trim_end_matches('/').to_string();
+                (name, e.header().mode().unwrap())
+            })
+            .collect();
+        modes.sort();
+        modes
+    }
+
+    fn expected_modes() -> Vec<(String, u32)> {
+        MODES.iter().map(|(name, mode)| (name.to_string(), *mode)).collect()
+    }
+
+    #[test]
+    fn same_tree_gives_the_same_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.tar", "a.tar.gz", "a.zip"] {
+            let (first, second) = (dir.path().join(name), dir.path().join(format!("again-{}", name)));
+            write_archive(&first, Path::new("out"), &entries()).unwrap();
+            write_archive(&second, Path::new("out"), &entries()).unwrap();
+            assert_eq!(fs::read(&first).unwrap(), fs::read(&second).unwrap(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn tar_modes_mark_directories_and_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.tar");
+        write_archive(&path, Path::new("out"), &entries()).unwrap();
+        assert_eq!(tar_modes(File::open(&path).unwrap()), expected_modes());
+    }
+
+    #[test]
+    fn tar_gz_modes_mark_directories_and_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.tgz");
+        write_archive(&path, Path::new("out"), &entries()).unwrap();
+        let decoded = flate2::read::GzDecoder::new(File::open(&path).unwrap());
+        assert_eq!(tar_modes(decoded), expected_modes());
+    }
+
+    #[test]
+    fn zip_modes_mark_directories_and_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.zip");
+        write_archive(&path, Path::new("out"), &entries()).unwrap();
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut modes: Vec<(String, u32)> = (0..archive.len())
+            .map(|i| {
+                let file = archive.by_index(i).unwrap();
+                (file.name().trim_end_matches('/').to_string(), file.unix_mode().unwrap() & 0o777)
+            })
+            .collect();
+        modes.sort();
+        assert_eq!(modes, expected_modes());
+    }
+
+    #[test]
+    fn replaces_an_existing_file_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.tar");
+        fs::write(&path, "old").unwrap();
+        write_archive(&path, Path::new("out"), &entries()).unwrap();
+        assert_eq!(tar_modes(File::open(&path).unwrap()).len(), MODES.len());
+        let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, ["a.tar"]);
+
+        fs::create_dir(dir.path().join("dir.tar")).unwrap();
+        let err = write_archive(&dir.path().join("dir.tar"), Path::new("out"), &entries()).unwrap_err();
+        assert!(err.to_string().contains("is a directory"), "{}", err);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::error::Result;
+use clap::ValueEnum;
+use colored::*;
+use ignore::overrides::OverrideBuilder;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::filesystem::{EntryKind, FileSystem};
+use crate::node::{Node, NodeKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+pub enum Finding {
+    Missing { path: PathBuf, kind: NodeKind },
+    WrongKind { path: PathBuf, expected: NodeKind, found: EntryKind },
+    // only reported in strict mode
+    Extra { path: PathBuf, found: EntryKind },
+}
+
+// Compares what is on `fs` under `base` with the collected structure
+pub fn check(fs: &dyn FileSystem, base: &Path, all_paths: &BTreeMap<PathBuf, Node>, strict: bool, exclude: &[String]) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    for (path, node) in all_paths {
+        match fs.kind(path) {
+            None => findings.push(Finding::Missing { path: path.clone(), kind: node.kind }),
+            Some(found) if found.node_kind() != Some(node.kind) => findings.push(Finding::WrongKind {
+                path: path.clone(),
+                expected: node.kind,
+                found,
+            }),
+            Some(_) => {}
+        }
+    }
+
+    if strict {
+        let mut overrides = OverrideBuilder::new(base);
+        overrides.add("!.git/")?;
+        for glob in exclude {
+            overrides.add(&format!("!{}", glob))?;
+        }
+        for path in fs.walk(base, &overrides.build()?)? {
+            let path = path.as_path();
+            if all_paths.contains_key(path) {
+                continue;
+            }
+            // only the topmost unexpected entry, not everything inside it
+            let parent_known = path
+                .parent()
+                .is_some_and(|p| p == base || all_paths.contains_key(p));
+            if parent_known {
+                findings.push(Finding::Extra {
+                    path: path.to_path_buf(),
+                    found: fs.kind(path).unwrap_or(EntryKind::Other),
+                });
+            }
+        }
+    }
+
+    Ok(findings)
+}
+
+fn relative(base: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(base).unwrap_or(path);
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub fn report(base: &Path, checked: usize, findings: &[Finding], format: Format) {
+    match format {
+        Format::Json => {
+            let mut missing = Vec::new();
+            let mut wrong_kind = Vec::new();
+            let mut extra = Vec::new();
+            for finding in findings {
+                match finding {
+                    Finding::Missing { path, kind } => missing.push(json!({
+                        "path": relative(base, path),
+                        "kind": kind.as_str(),
+                    })),
+                    Finding::WrongKind { path, expected, found } => wrong_kind.push(json!({
+                        "path": relative(base, path),
+                        "expected": expected.as_str(),
+                        "found": found.as_str(),
+                    })),
+                    Finding::Extra { path, found } => extra.push(json!({
+                        "path": relative(base, path),
+                        "kind": found.as_str(),
+                    })),
+                }
+            }
+            let out = json!({
+                "ok": findings.is_empty(),
+                "checked": checked,
+                "missing": missing,
+                "wrong_kind": wrong_kind,
+                "extra": extra,
+            });
+            println!("{}", serde_json::to_string_pretty(&out).unwrap_or_default());
+        }
+        Format::Text => {
+            for finding in findings {
+                match finding {
+                    Finding::Missing { path, kind } => {
+                        println!("{} {} ({})", "missing   ".red(), relative(base, path), kind.as_str())
+                    }
+                    Finding::WrongKind { path, expected, found } => println!(
+                        "{} {} (expected {}, found {})",
+                        "wrong kind".red(),
+                        relative(base, path),
+                        expected.as_str(),
+                        found.as_str()
+                    ),
+                    Finding::Extra { path, found } => {
+                        println!("{} {} ({})", "extra     ".yellow(), relative(base, path), found.as_str())
+                    }
+                }
+            }
+            if findings.is_empty() {
+                println!("{} '{}' matches the structure ({} entries checked)", "OK:".green(), base.display(), checked);
+            } else {
+                println!(
+                    "\n{} {} problem(s) found in '{}'",
+                    "Error:".red(),
+                    findings.len(),
+                    base.display()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFs;
+
+    #[test]
+    fn reports_missing_entries_and_kind_mismatches() {
+        let fs = MemoryFs::new();
+        fs.create_dir(Path::new("src")).unwrap();
+        fs.write(Path::new("src/lib.rs"), b"").unwrap();
+        fs.write(Path::new("docs"), b"").unwrap();
+
+        let all_paths: BTreeMap<PathBuf, Node> = [Node::dir("src"), Node::file("src/lib.rs"), Node::dir("docs"), Node::file("README.md")]
+            .into_iter()
+            .map(|n| (n.path.clone(), n))
+            .collect();
+        let findings = check(&fs, Path::new(""), &all_paths, false, &[]).unwrap();
+
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().any(|f| matches!(f,
+            Finding::WrongKind { path, expected: NodeKind::Dir, found: EntryKind::File } if path == Path::new("docs"))));
+        assert!(findings.iter().any(|f| matches!(f,
+            Finding::Missing { path, kind: NodeKind::File } if path == Path::new("README.md"))));
+    }
+}
@@ -0,0 +1,92 @@
+use crate::error::{Context, Error, Result};
+use clap::ValueEnum;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+// What to do when an entry already exists on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConflictPolicy {
+    // leave it alone, what is missing inside a directory is still created
+    Skip,
+    // replace it
+    Overwrite,
+    // stop and roll back
+    Error,
+    // rename it to <name>.bak and create a fresh one
+    Backup,
+    // keep it, but still create whatever is missing inside it
+    Merge,
+}
+
+pub struct Resolver {
+    // None means ask the user each time
+    policy: Option<ConflictPolicy>,
+    force_replace_dirs: bool,
+}
+
+impl Resolver {
+    // without an explicit policy we prompt, unless prompting is impossible or
+    // unwanted, in which case we merge since it never destroys anything
+    pub fn new(policy: Option<ConflictPolicy>, non_interactive: bool, force_replace_dirs: bool) -> Self {
+        let can_prompt = !non_interactive && io::stdin().is_terminal();
+        let policy = match policy {
+            Some(p) => Some(p),
+            None if can_prompt => None,
+            None => Some(ConflictPolicy::Merge),
+        };
+        Resolver { policy, force_replace_dirs }
+    }
+
+    // the policy applied to every conflict, None while the user is still asked
+    pub fn policy(&self) -> Option<ConflictPolicy> {
+        self.policy
+    }
+
+    // existing directories are only ever conflicts when replacing them was asked for
+    pub fn force_replace_dirs(&self) -> bool {
+        self.force_replace_dirs
+    }
+
+    pub fn resolve(&mut self, path: &Path) -> Result<ConflictPolicy> {
+        match self.policy {
+            Some(policy) => Ok(policy),
+            None => self.ask(path),
+        }
+    }
+
+    fn ask(&mut self, path: &Path) -> Result<ConflictPolicy> {
+        println!("Warning '{}' already exists.", path.display());
+        loop {
+            print!("Overwrite (o), skip (s), backup (b), merge (m) or cancel (c)? Use a capital letter to apply to all [o/s/b/m/c]: ");
+            io::stdout().flush().with_context(|| "Cannot write to the terminal".to_string())?; // Make sure prompt shows
+
+            let mut answer = String::new();
+            let read = io::stdin().read_line(&mut answer).with_context(|| "Cannot read the answer".to_string())?;
+            if read == 0 {
+                // stdin closed, nobody left to ask
+                return Err(Error::Cancelled);
+            }
+            let answer = answer.trim();
+
+            let (choice, all) = match answer {
+                "o" | "overwrite" => (ConflictPolicy::Overwrite, false),
+                "s" | "skip" | "" => (ConflictPolicy::Skip, false),
+                "b" | "backup" => (ConflictPolicy::Backup, false),
+                "m" | "merge" => (ConflictPolicy::Merge, false),
+                "O" | "overwrite-all" => (ConflictPolicy::Overwrite, true),
+                "S" | "skip-all" => (ConflictPolicy::Skip, true),
+                "B" | "backup-all" => (ConflictPolicy::Backup, true),
+                "M" | "merge-all" => (ConflictPolicy::Merge, true),
+                "c" | "C" | "cancel" => return Err(Error::Cancelled),
+                _ => {
+                    println!("Unknown option '{}'.", answer);
+                    continue;
+                }
+            };
+            if all {
+                self.policy = Some(choice);
+            }
+            return Ok(choice);
+        }
+    }
+}
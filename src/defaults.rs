@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use crate::error::Result;
+use crate::parse::{ParseOptions, parse_structure};
+use crate::spec::Spec;
+
+// A structure compiled into the binary for --default, written like any template
+// file (see defaults/ in the source tree). Installed templates of the same name
+// take precedence, so each one can be replaced.
+pub struct Builtin {
+    pub name: &'static str,
+    // older and shorter names it also answers to
+    pub aliases: &'static [&'static str],
+    source: &'static str,
+}
+
+macro_rules! builtin {
+    ($name:literal $(, $alias:literal)*) => {
+        Builtin {
+            name: $name,
+            aliases: &[$($alias),*],
+            source: include_str!(concat!("../defaults/", $name, ".txt")),
+        }
+    };
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    builtin!("rust-bin", "rs", "rust"),
+    builtin!("rust-lib"),
+    builtin!("rust-workspace"),
+    builtin!("python", "py"),
+    builtin!("go", "golang"),
+    builtin!("node", "js", "javascript"),
+    builtin!("typescript", "ts"),
+    builtin!("web"),
+    builtin!("c-cmake", "c", "cmake"),
+    builtin!("java-maven", "java", "maven"),
+];
+
+// by name or alias
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|b| b.name == name || b.aliases.contains(&name))
+}
+
+impl Builtin {
+    pub fn spec(&self, opts: &ParseOptions) -> Result<Spec> {
+        let path = format!("defaults/{}.txt", self.name);
+        parse_structure(self.source, Path::new(&path), 1, opts)
+    }
+
+    // a built-in that doesn't parse is a bug, not a missing description
+    pub fn description(&self) -> Result<Option<String>> {
+        Ok(self.spec(&ParseOptions::default())?.header.description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_builtin_parses() {
+        for builtin in BUILTINS {
+            let spec = builtin.spec(&ParseOptions::default()).unwrap_or_else(|e| panic!("{}: {}", builtin.name, e));
+            assert!(!spec.nodes.is_empty(), "{}", builtin.name);
+            assert!(builtin.description().unwrap().is_some(), "{} has no description", builtin.name);
+        }
+    }
+
+    #[test]
+    fn names_and_aliases_are_unique() {
+        for builtin in BUILTINS {
+            for name in std::iter::once(builtin.name).chain(builtin.aliases.iter().copied()) {
+                assert_eq!(find(name).map(|b| b.name), Some(builtin.name), "'{}' is taken twice", name);
+            }
+        }
+    }
+}
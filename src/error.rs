@@ -0,0 +1,82 @@
+use std::io;
+use std::path::PathBuf;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// Everything that can go wrong reading a structure or creating it
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    // a structure file that doesn't follow the format, "file:line:column: message"
+    #[error("{}:{line}:{column}: {message}", path.display())]
+    Syntax { path: PathBuf, line: usize, column: usize, message: String },
+
+    // reading or writing something failed, `context` says what
+    #[error("{context}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+
+    // walking a directory, or a bad exclude glob
+    #[error(transparent)]
+    Walk(#[from] ignore::Error),
+
+    // input that makes no sense: bad names, unknown templates, include cycles, conflicts...
+    #[error("{0}")]
+    Invalid(String),
+
+    // what was being done when `source` went wrong
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<Error>,
+    },
+
+    // the user chose to cancel at a prompt
+    #[error("operation cancelled")]
+    Cancelled,
+}
+
+impl Error {
+    // true if this, or the error it wraps, is a cancelled prompt
+    pub fn is_cancelled(&self) -> bool {
+        match self {
+            Error::Cancelled => true,
+            Error::Context { source, .. } => source.is_cancelled(),
+            _ => false,
+        }
+    }
+}
+
+// `.with_context(|| ...)` as in anyhow, for io errors and our own
+pub(crate) trait Context<T> {
+    fn with_context(self, f: impl FnOnce() -> String) -> Result<T>;
+}
+
+impl<T> Context<T> for Result<T, io::Error> {
+    fn with_context(self, f: impl FnOnce() -> String) -> Result<T> {
+        self.map_err(|source| Error::Io { context: f(), source })
+    }
+}
+
+impl<T> Context<T> for Result<T, ignore::Error> {
+    fn with_context(self, f: impl FnOnce() -> String) -> Result<T> {
+        self.map_err(|source| Error::Context { context: f(), source: Box::new(Error::Walk(source)) })
+    }
+}
+
+impl<T> Context<T> for Result<T> {
+    fn with_context(self, f: impl FnOnce() -> String) -> Result<T> {
+        self.map_err(|source| Error::Context { context: f(), source: Box::new(source) })
+    }
+}
+
+// return early with an Error::Invalid, formatted like `format!`
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err($crate::error::Error::Invalid(format!($($arg)*)))
+    };
+}
+pub(crate) use bail;
@@ -0,0 +1,175 @@
+use crate::error::{Result, bail};
+
+// Names in structure files and arguments can be quoted or escaped like in a shell:
+//
+//   "notes .txt "      spaces, trailing ones included
+//   \#draft.md         a name starting with '#', which would be a comment
+//   "!important"       or with '!', which would be a directive
+//   "{a,b}.txt"        no brace expansion inside quotes
+//   "my docs"/         '/' keeps its meaning, this is a directory
+//
+// A backslash makes the next character literal, inside quotes or not.
+// Quoted parts are turned into backslash escapes first (quotes_to_escapes), so
+// brace expansion only has to step over escaped characters, and unescape then
+// gives the final name.
+
+// Cuts off a `#` comment: everything from an unquoted, unescaped '#' at the
+// start or after whitespace. "C#/" keeps its '#'.
+pub fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut after_blank = true;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            after_blank = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes && after_blank => return &line[..i],
+            _ => {}
+        }
+        after_blank = c.is_whitespace();
+    }
+    line
+}
+
+// true when `text` stops in the middle of a quoted part
+pub fn ends_in_quotes(text: &str) -> bool {
+    let mut in_quotes = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            _ => {}
+        }
+    }
+    in_quotes
+}
+
+// `"a b"/{c,d}` -> `a\ b/{c,d}`
+pub fn quotes_to_escapes(text: &str) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut in_quotes = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) => {
+                    out.push('\\');
+                    out.push(next);
+                }
+                None => bail!("'{}' ends in a lone backslash", text),
+            },
+            '"' => in_quotes = !in_quotes,
+            '/' => out.push('/'),
+            c if in_quotes && !c.is_alphanumeric() => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    if in_quotes {
+        bail!("'{}' has an unclosed quote", text);
+    }
+    Ok(out)
+}
+
+// Resolves escapes and drops the unescaped whitespace around the name.
+// Returns the name without its trailing '/' and whether it had one.
+pub fn unescape(text: &str) -> Result<(String, bool)> {
+    let mut out = String::with_capacity(text.len());
+    // length of `out` up to the last character that isn't plain whitespace
+    let mut keep = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('/') => bail!("'{}': '/' can't be part of a name, it always separates directories", text),
+                Some(next) => {
+                    out.push(next);
+                    keep = out.len();
+                }
+                None => bail!("'{}' ends in a lone backslash", text),
+            },
+            c if c.is_whitespace() => {
+                if !out.is_empty() {
+                    out.push(c);
+                }
+            }
+            c => {
+                out.push(c);
+                keep = out.len();
+            }
+        }
+    }
+    out.truncate(keep);
+    let is_dir = out.ends_with('/');
+    let name = out.trim_end_matches('/').to_string();
+    Ok((name, is_dir))
+}
+
+// How to write `name` in a structure file so it reads back unchanged
+pub fn quote_name(name: &str) -> String {
+    let plain = !name.is_empty()
+        && name.trim() == name
+        && strip_comment(name) == name
+        && !name.starts_with('!')
+        && !name.contains(['"', '\\', '{', '}'])
+        && !name.contains(" <<")
+        && !name.contains(" @");
+    if plain {
+        return name.to_string();
+    }
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments() {
+        assert_eq!(strip_comment("main.rs # entry point"), "main.rs ");
+        assert_eq!(strip_comment("# whole line"), "");
+        assert_eq!(strip_comment("C#/"), "C#/");
+        assert_eq!(strip_comment("\\#draft.md"), "\\#draft.md");
+        assert_eq!(strip_comment("\"# not a comment\" # but this is"), "\"# not a comment\" ");
+    }
+
+    #[test]
+    fn quotes_become_escapes() {
+        assert_eq!(quotes_to_escapes("\"a b\"/{c,d}").unwrap(), "a\\ b/{c,d}");
+        assert_eq!(quotes_to_escapes("\"{a,b}.txt\"").unwrap(), "\\{a\\,b\\}\\.txt");
+        assert_eq!(quotes_to_escapes("plain\\ name").unwrap(), "plain\\ name");
+        assert!(quotes_to_escapes("\"open").is_err());
+        assert!(quotes_to_escapes("trailing\\").is_err());
+        assert!(ends_in_quotes("name \"still open"));
+        assert!(!ends_in_quotes("\"closed\" \\\""));
+    }
+
+    #[test]
+    fn unescaping() {
+        assert_eq!(unescape("  a\\ b  ").unwrap(), ("a b".to_string(), false));
+        assert_eq!(unescape("notes\\ ").unwrap(), ("notes ".to_string(), false));
+        assert_eq!(unescape("docs/").unwrap(), ("docs".to_string(), true));
+        assert_eq!(unescape("\\#x\\{y\\}").unwrap(), ("#x{y}".to_string(), false));
+        assert!(unescape("a\\/b").is_err());
+    }
+
+    #[test]
+    fn quoted_names_read_back() {
+        for name in ["plain.txt", " padded ", "#draft", "!important", "{a,b}", "say \"hi\"", "back\\slash", "a <<EOF", ""] {
+            let quoted = quote_name(name);
+            let read = unescape(&quotes_to_escapes(&quoted).unwrap()).unwrap();
+            assert_eq!(read, (name.to_string(), false), "{}", quoted);
+        }
+        assert_eq!(quote_name("plain.txt"), "plain.txt");
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::conflict::{ConflictPolicy, Resolver};
+use crate::error::{Result, bail};
+use crate::filesystem::FileSystem;
+use crate::node::{Node, NodeKind};
+use crate::transaction::Journal;
+
+// What a run did about entries that already existed, as it happens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Skipped(PathBuf),
+    // a file kept as it was by the merge policy
+    KeptExisting(PathBuf),
+    BackedUp { path: PathBuf, to: PathBuf },
+    // an overwritten directory is about to take these entries with it
+    Replacing { dir: PathBuf, lost: Vec<PathBuf> },
+    // cleaning up after a successful run went wrong
+    Warning(String),
+}
+
+// Creates collected entries (see plan::collect_groups) on a file system, letting the
+// resolver decide about anything that already exists. Every change goes into a
+// journal so a failed or interrupted run can be undone.
+pub struct Executor {
+    fs: Arc<dyn FileSystem>,
+    resolver: Resolver,
+    journal: Arc<Mutex<Journal>>,
+    on_event: Box<dyn FnMut(Event)>,
+}
+
+impl Executor {
+    // with `rollback`, overwritten entries are kept aside until the run is committed
+    pub fn new(fs: Arc<dyn FileSystem>, resolver: Resolver, rollback: bool) -> Self {
+        let journal = Arc::new(Mutex::new(Journal::new(Arc::clone(&fs), rollback)));
+        Executor { fs, resolver, journal, on_event: Box::new(drop) }
+    }
+
+    // called with every Event of a run, which are dropped otherwise
+    pub fn on_event(mut self, f: impl FnMut(Event) + 'static) -> Self {
+        self.on_event = Box::new(f);
+        self
+    }
+
+    // shared with whoever has to undo an interrupted run, like a Ctrl-C handler
+    pub fn journal(&self) -> Arc<Mutex<Journal>> {
+        Arc::clone(&self.journal)
+    }
+
+    // Creates every entry in path order, stopping at the first failure.
+    // A successful run is committed. After a failure nothing is undone yet:
+    // call rollback() to remove what this run did, or commit() to keep it.
+    pub fn run(&mut self, all_paths: &BTreeMap<PathBuf, Node>) -> Result<()> {
+        for node in all_paths.values() {
+            self.create_path(node)?;
+        }
+        for error in self.commit() {
+            (self.on_event)(Event::Warning(error));
+        }
+        Ok(())
+    }
+
+    pub fn created_count(&self) -> usize {
+        lock(&self.journal).created_count()
+    }
+
+    // keeps this run, returns the backups of overwritten entries that couldn't be removed
+    pub fn commit(&self) -> Vec<String> {
+        lock(&self.journal).commit()
+    }
+
+    // undoes this run, returns what couldn't be undone
+    pub fn rollback(&self) -> Vec<String> {
+        lock(&self.journal).rollback()
+    }
+
+    // Creates one entry, unless it exists and the conflict policy keeps it
+    fn create_path(&mut self, node: &Node) -> Result<()> {
+        let (path, kind) = (node.path.as_path(), node.kind);
+        let mut overwrite = false;
+        let mut backup = false;
+
+        // An existing directory declared as a directory is kept and filled in,
+        // replacing it wholesale has to be asked for with --force-replace-dirs
+        if self.fs.is_dir(path) && kind == NodeKind::Dir && !self.resolver.force_replace_dirs() {
+            return Ok(());
+        }
+
+        // If it already exists, the conflict policy decides (possibly by asking)
+        if self.fs.exists(path) {
+            let rel = path.display();
+            match self.resolver.resolve(path)? {
+                ConflictPolicy::Skip => {
+                    (self.on_event)(Event::Skipped(path.to_path_buf()));
+                    return Ok(());
+                }
+                ConflictPolicy::Merge => {
+                    if self.fs.is_dir(path) != (kind == NodeKind::Dir) {
+                        bail!("Cannot merge '{}': it exists with a different type than declared", rel);
+                    }
+                    if kind == NodeKind::File {
+                        (self.on_event)(Event::KeptExisting(path.to_path_buf()));
+                    }
+                    return Ok(());
+                }
+                ConflictPolicy::Error => {
+                    bail!("'{}' already exists", rel);
+                }
+                ConflictPolicy::Overwrite => overwrite = true,
+                ConflictPolicy::Backup => backup = true,
+            }
+
+            if overwrite && self.fs.is_dir(path) {
+                let lost = dir_contents(self.fs.as_ref(), path);
+                if !lost.is_empty() {
+                    if !self.resolver.force_replace_dirs() {
+                        bail!(
+                            "Refusing to replace non-empty directory '{}' ({} entries), pass --force-replace-dirs to allow it",
+                            rel,
+                            lost.len()
+                        );
+                    }
+                    (self.on_event)(Event::Replacing { dir: path.to_path_buf(), lost });
+                }
+            }
+        }
+
+        // Hold the journal while touching the disk so Ctrl-C can't roll back mid-operation
+        let mut journal = lock(&self.journal);
+        if overwrite {
+            journal.remove_existing(path)?;
+        }
+        if backup {
+            let to = journal.backup(path)?;
+            (self.on_event)(Event::BackedUp { path: path.to_path_buf(), to });
+        }
+
+        // Make sure parent directories exist
+        if let Some(parent) = path.parent() {
+            journal.create_dir_all(parent)?;
+        }
+
+        // Create either a directory or file
+        match kind {
+            NodeKind::File => journal.create_file(path, node.content.as_deref())?,
+            NodeKind::Dir => journal.create_dir_all(path)?,
+        }
+
+        Ok(())
+    }
+}
+
+
+// a panic elsewhere shouldn't stop a rollback
+pub fn lock(journal: &Mutex<Journal>) -> MutexGuard<'_, Journal> {
+    journal.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+
+// Everything inside a directory, depth first
+fn dir_contents(fs: &dyn FileSystem, dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let Ok(entries) = fs.read_dir(dir) else {
+        return found;
+    };
+    for entry in entries {
+        let is_dir = fs.is_dir(&entry) && !fs.is_symlink(&entry);
+        found.push(entry.clone());
+        if is_dir {
+            found.extend(dir_contents(fs, &entry));
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conflict::ConflictPolicy;
+    use crate::filesystem::MemoryFs;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn entries(nodes: Vec<Node>) -> BTreeMap<PathBuf, Node> {
+        nodes.into_iter().map(|n| (n.path.clone(), n)).collect()
+    }
+
+    fn with_content(path: &str, content: &str) -> Node {
+        Node { content: Some(content.as_bytes().to_vec()), ..Node::file(path) }
+    }
+
+    fn executor(fs: &Arc<MemoryFs>, policy: ConflictPolicy) -> Executor {
+        Executor::new(Arc::clone(fs) as Arc<dyn FileSystem>, Resolver::new(Some(policy), true, false), true)
+    }
+
+    #[test]
+    fn creates_entries_and_parents() {
+        let fs = Arc::new(MemoryFs::new());
+        let mut exec = executor(&fs, ConflictPolicy::Error);
+        exec.run(&entries(vec![Node::file("src/bin/main.rs"), with_content("README.md", "hi\n")])).unwrap();
+        assert_eq!(fs.paths(), [PathBuf::from("README.md"), "src".into(), "src/bin".into(), "src/bin/main.rs".into()]);
+        assert_eq!(fs.read(Path::new("README.md")).as_deref(), Some("hi\n".as_bytes()));
+    }
+
+    #[test]
+    fn rollback_removes_what_a_failed_run_created() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.write(Path::new("z.txt"), b"keep").unwrap();
+        let mut exec = executor(&fs, ConflictPolicy::Error);
+        let err = exec.run(&entries(vec![Node::dir("a"), Node::file("a/b.txt"), Node::file("z.txt")])).unwrap_err();
+        assert!(err.to_string().contains("already exists"), "{}", err);
+        assert_eq!(exec.created_count(), 2);
+
+        assert!(exec.rollback().is_empty());
+        assert_eq!(fs.paths(), [PathBuf::from("z.txt")]);
+        assert_eq!(fs.read(Path::new("z.txt")).as_deref(), Some("keep".as_bytes()));
+    }
+
+    #[test]
+    fn rollback_restores_overwritten_entries() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.write(Path::new("a.txt"), b"old").unwrap();
+        fs.create_dir(Path::new("b")).unwrap();
+        fs.write(Path::new("b/keep.txt"), b"").unwrap();
+        let before = fs.paths();
+
+        // replacing the non-empty directory `b` needs --force-replace-dirs, so the run stops there
+        let mut exec = executor(&fs, ConflictPolicy::Overwrite);
+        let err = exec.run(&entries(vec![with_content("a.txt", "new"), Node::file("b")])).unwrap_err();
+        assert!(err.to_string().contains("Refusing to replace"), "{}", err);
+        assert_eq!(fs.read(Path::new("a.txt")).as_deref(), Some("new".as_bytes()));
+
+        assert!(exec.rollback().is_empty());
+        assert_eq!(fs.paths(), before);
+        assert_eq!(fs.read(Path::new("a.txt")).as_deref(), Some("old".as_bytes()));
+    }
+
+    #[test]
+    fn committed_overwrites_leave_no_backups() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.write(Path::new("a.txt"), b"old").unwrap();
+        let mut exec = executor(&fs, ConflictPolicy::Overwrite);
+        exec.run(&entries(vec![with_content("a.txt", "new")])).unwrap();
+        assert_eq!(fs.paths(), [PathBuf::from("a.txt")]);
+        assert_eq!(fs.read(Path::new("a.txt")).as_deref(), Some("new".as_bytes()));
+    }
+
+    #[test]
+    fn reports_events() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.write(Path::new("a.txt"), b"old").unwrap();
+        fs.write(Path::new("a.txt.bak"), b"older").unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let seen = Rc::clone(&events);
+        let mut exec = executor(&fs, ConflictPolicy::Backup).on_event(move |e| seen.borrow_mut().push(e));
+        exec.run(&entries(vec![Node::file("a.txt")])).unwrap();
+
+        assert_eq!(*events.borrow(), [Event::BackedUp { path: "a.txt".into(), to: "a.txt.bak1".into() }]);
+        assert_eq!(fs.read(Path::new("a.txt.bak1")).as_deref(), Some("old".as_bytes()));
+        assert_eq!(fs.read(Path::new("a.txt")).as_deref(), Some("".as_bytes()));
+
+        events.borrow_mut().clear();
+        let seen = Rc::clone(&events);
+        let mut exec = executor(&fs, ConflictPolicy::Skip).on_event(move |e| seen.borrow_mut().push(e));
+        exec.run(&entries(vec![Node::file("a.txt"), Node::file("new.txt")])).unwrap();
+        assert_eq!(*events.borrow(), [Event::Skipped("a.txt".into())]);
+        assert!(fs.exists(Path::new("new.txt")));
+    }
+
+    #[test]
+    fn skipped_directories_still_get_their_missing_entries() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.create_dir(Path::new("src")).unwrap();
+        fs.write(Path::new("src/old.rs"), b"old").unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let seen = Rc::clone(&events);
+        let resolver = Resolver::new(Some(ConflictPolicy::Skip), true, true);
+        let mut exec = Executor::new(Arc::clone(&fs) as Arc<dyn FileSystem>, resolver, true)
+            .on_event(move |e| seen.borrow_mut().push(e));
+        exec.run(&entries(vec![Node::dir("src"), with_content("src/old.rs", "new"), Node::file("src/new.rs")]))
+            .unwrap();
+
+        assert_eq!(*events.borrow(), [Event::Skipped("src".into()), Event::Skipped("src/old.rs".into())]);
+        assert_eq!(fs.read(Path::new("src/old.rs")).as_deref(), Some("old".as_bytes()));
+        assert!(fs.exists(Path::new("src/new.rs")));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::error::{Error, Result, bail};
+
+// more than this many names from one token is almost certainly a typo
+const MAX_EXPANSIONS: usize = 10_000;
+
+// Shell-like brace expansion of a single name or path:
+//
+//   src/{api,db}/mod.rs     -> src/api/mod.rs, src/db/mod.rs
+//   {001..003}.sql          -> 001.sql, 002.sql, 003.sql (zero padding is kept)
+//   {a..c}, {0..10..5}      -> letters, numbers with a step
+//   {x,y{1,2}}              -> x, y1, y2
+//
+// Braces without a comma or range (`{foo}`), escaped ones (`\{a,b\}`) and
+// {{placeholders}} stay as they are.
+pub fn expand_braces(input: &str) -> Result<Vec<String>> {
+    let out = expand(input)?;
+    if out.len() > MAX_EXPANSIONS {
+        bail!("'{}' expands to more than {} names", input, MAX_EXPANSIONS);
+    }
+    Ok(out)
+}
+
+fn expand(input: &str) -> Result<Vec<String>> {
+    let Some((start, end)) = find_group(input) else {
+        return Ok(vec![input.to_string()]);
+    };
+    let prefix = &input[..start];
+    let body = &input[start + 1..end];
+    let suffix = expand(&input[end + 1..])?;
+
+    let alternatives = match range(body) {
+        Some(r) => r?,
+        None => {
+            let mut alts = Vec::new();
+            for part in split_top_level(body) {
+                alts.extend(expand(part)?);
+            }
+            alts
+        }
+    };
+
+    let mut out = Vec::with_capacity(alternatives.len() * suffix.len());
+    for alt in &alternatives {
+        for rest in &suffix {
+            out.push(format!("{}{}{}", prefix, alt, rest));
+            if out.len() > MAX_EXPANSIONS {
+                return Ok(out);
+            }
+        }
+    }
+    Ok(out)
+}
+
+// Byte offsets of the first `{...}` that actually expands.
+// Backslash-escaped characters (see escape.rs) never take part.
+fn find_group(s: &str) -> Option<(usize, usize)> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i..].starts_with(b"{{") {
+            // {{placeholder}}, leave it for variable substitution
+            match s[i..].find("}}") {
+                Some(close) => {
+                    i += close + 2;
+                    continue;
+                }
+                None => return None,
+            }
+        }
+        if bytes[i] == b'{'
+            && let Some(end) = matching_brace(s, i)
+        {
+            let body = &s[i + 1..end];
+            if split_top_level(body).len() > 1 || range(body).is_some() {
+                return Some((i, end));
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+fn matching_brace(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, b) in s.bytes().enumerate().skip(open) {
+        if std::mem::take(&mut escaped) {
+            continue;
+        }
+        match b {
+            b'\\' => escaped = true,
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn split_top_level(body: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut last = 0;
+    let mut escaped = false;
+    for (i, b) in body.bytes().enumerate() {
+        if std::mem::take(&mut escaped) {
+            continue;
+        }
+        match b {
+            b'\\' => escaped = true,
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            b',' if depth == 0 => {
+                parts.push(&body[last..i]);
+                last = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&body[last..]);
+    parts
+}
+
+// `a..b` or `a..b..step`, numbers or single letters. None if it isn't a range.
+fn range(body: &str) -> Option<Result<Vec<String>>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    if parts.len() != 2 && parts.len() != 3 {
+        return None;
+    }
+    let step: i64 = match parts.get(2) {
+        Some(s) => match s.parse::<i64>().ok()?.checked_abs() {
+            Some(step) => step,
+            None => return Some(Err(Error::Invalid(format!("range step is too large in '{{{}}}'", body)))),
+        },
+        None => 1,
+    };
+    if step == 0 {
+        return Some(Err(Error::Invalid(format!("range step can't be zero in '{{{}}}'", body))));
+    }
+
+    let (from, to) = (parts[0], parts[1]);
+    if let (Ok(a), Ok(b)) = (from.parse::<i64>(), to.parse::<i64>()) {
+        // 001..010 keeps its width
+        let padded = |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+        let width = if padded(from) || padded(to) { from.len().max(to.len()) } else { 0 };
+        // steps between the ends, one less than the names
+        if a.abs_diff(b) / step as u64 >= MAX_EXPANSIONS as u64 {
+            return Some(Err(Error::Invalid(format!("'{{{}}}' expands to more than {} names", body, MAX_EXPANSIONS))));
+        }
+        let values = stepped(a, b, step).map(|n| format!("{:0width$}", n, width = width));
+        return Some(Ok(values.collect()));
+    }
+
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() => Some(c),
+            _ => None,
+        }
+    };
+    let (a, b) = (single(from)?, single(to)?);
+    let values = stepped(a as i64, b as i64, step).map(|n| ((n as u8) as char).to_string());
+    Some(Ok(values.collect()))
+}
+
+// wide enough that ends near i64::MIN and i64::MAX can't overflow in between
+fn stepped(a: i64, b: i64, step: i64) -> impl Iterator<Item = i64> {
+    let count = a.abs_diff(b) / step as u64 + 1;
+    let step = if a <= b { i128::from(step) } else { -i128::from(step) };
+    (0..count).map(move |k| (i128::from(a) + i128::from(k) * step) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_near_the_integer_limits_dont_overflow() {
+        assert!(expand_braces("x/{-9223372036854775808..9223372036854775807}").is_err());
+        assert!(expand_braces("{1..5..-9223372036854775808}").is_err());
+        assert_eq!(
+            expand_braces("{9223372036854775806..9223372036854775807}").unwrap(),
+            ["9223372036854775806", "9223372036854775807"]
+        );
+        assert_eq!(
+            expand_braces("{-9223372036854775808..9223372036854775807..9223372036854775807}").unwrap(),
+            ["-9223372036854775808", "-1", "9223372036854775806"]
+        );
+    }
+
+    #[test]
+    fn lists_and_nesting() {
+        assert_eq!(expand_braces("src/{api,db}/mod.rs").unwrap(), ["src/api/mod.rs", "src/db/mod.rs"]);
+        assert_eq!(expand_braces("{x,y{1,2}}").unwrap(), ["x", "y1", "y2"]);
+        assert_eq!(expand_braces("{a,b}{1,2}").unwrap(), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand_braces("{,.}env").unwrap(), ["env", ".env"]);
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(expand_braces("{1..3}").unwrap(), ["1", "2", "3"]);
+        assert_eq!(expand_braces("{3..1}").unwrap(), ["3", "2", "1"]);
+        assert_eq!(expand_braces("{001..003}.sql").unwrap(), ["001.sql", "002.sql", "003.sql"]);
+        assert_eq!(expand_braces("{0..10..5}").unwrap(), ["0", "5", "10"]);
+        assert_eq!(expand_braces("{10..0..-5}").unwrap(), ["10", "5", "0"]);
+        assert_eq!(expand_braces("{a..c}").unwrap(), ["a", "b", "c"]);
+        assert_eq!(expand_braces("{-1..1}").unwrap(), ["-1", "0", "1"]);
+        assert!(expand_braces("{1..3..0}").is_err());
+        assert!(expand_braces("{0..100000}").is_err());
+        assert!(expand_braces("{0..99}{0..999}").is_err());
+    }
+
+    #[test]
+    fn what_stays_as_written() {
+        for literal in ["{foo}", "plain.txt", "{{name}}.rs", "{{a,b}}", "\\{a,b\\}", "{a\\,b}", "{1..}", "{a..zz}"] {
+            assert_eq!(expand_braces(literal).unwrap(), [literal], "{}", literal);
+        }
+        assert_eq!(expand_braces("{{name}}/{a,b}").unwrap(), ["{{name}}/a", "{{name}}/b"]);
+        assert_eq!(expand_braces("{a\\,b,c}").unwrap(), ["a\\,b", "c"]);
+    }
+}
@@ -0,0 +1,314 @@
+use flate2::read::GzDecoder;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+use crate::archive::ArchiveFormat;
+use crate::error::{Context, Result, bail};
+use crate::formats::parse_spec_file;
+use crate::include;
+use crate::node::Node;
+use crate::parse::ParseOptions;
+use crate::spec::Spec;
+
+const SCHEME: &str = "file://";
+
+// A template that lives outside the template directories, in an archive or a
+// local git repository:
+//
+//   file:///srv/scaffolds/rust.tar.gz         .tar, .tar.gz, .tgz or .zip
+//   file:///srv/scaffolds.git                 a git repository (bare or not), at HEAD
+//   file:///srv/scaffolds.git#v2              at a branch, tag or commit
+//   file:///srv/scaffolds.git//rust/cli#v2    only that part of it
+//
+// The part after '//' may also name a structure file inside, which is then read
+// like an installed template. Anything else is a scaffold: its files, contents
+// included, are the structure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateUrl {
+    pub path: PathBuf,
+    pub subdir: Option<PathBuf>,
+    pub reference: Option<String>,
+}
+
+pub fn is_url(name: &str) -> bool {
+    name.starts_with(SCHEME)
+}
+
+impl TemplateUrl {
+    pub fn parse(url: &str) -> Result<TemplateUrl> {
+        let Some(rest) = url.strip_prefix(SCHEME) else {
+            bail!("'{}' is not a {} URL", url, SCHEME);
+        };
+        let (rest, reference) = match rest.rsplit_once('#') {
+            Some((rest, reference)) if !reference.is_empty() => (rest, Some(reference.to_string())),
+            Some((rest, _)) => (rest, None),
+            None => (rest, None),
+        };
+        // the first '//' that isn't the leading one of the path
+        let (path, subdir) = match rest.get(1..).and_then(|r| r.find("//")) {
+            Some(i) => (&rest[..i + 1], Some(rest[i + 3..].trim_end_matches('/'))),
+            None => (rest, None),
+        };
+        if path.is_empty() {
+            bail!("'{}' doesn't name an archive or repository", url);
+        }
+
+        let subdir = subdir.filter(|s| !s.is_empty()).map(PathBuf::from);
+        if let Some(subdir) = &subdir
+            && subdir.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            bail!("'{}': the part after '//' has to stay inside the archive or repository", url);
+        }
+        Ok(TemplateUrl { path: PathBuf::from(path), subdir, reference })
+    }
+}
+
+// The structure a URL stands for. `find_template` looks up the templates a
+// structure file inside extends or includes, as for installed templates.
+pub fn load(url: &TemplateUrl, find_template: &dyn Fn(&str) -> Option<PathBuf>, opts: &ParseOptions) -> Result<Spec> {
+    let root = fetch(url)?;
+    let target = match &url.subdir {
+        Some(subdir) => root.join(subdir),
+        None => root.clone(),
+    };
+    // the part after '//' may itself be a link out of the template
+    if let (Ok(root), Ok(resolved)) = (fs::canonicalize(&root), fs::canonicalize(&target))
+        && !resolved.starts_with(&root)
+    {
+        bail!(
+            "'{}' points outside of '{}'",
+            url.subdir.as_deref().unwrap_or(Path::new("")).display(),
+            url.path.display()
+        );
+    }
+
+    if target.is_file() {
+        let spec = parse_spec_file(&target, None, opts)?;
+        return include::resolve(spec, &target, find_template, opts);
+    }
+    if !target.is_dir() {
+        bail!(
+            "'{}' has no '{}'",
+            url.path.display(),
+            url.subdir.as_deref().unwrap_or(Path::new("")).display()
+        );
+    }
+
+    let mut nodes = Vec::new();
+    scaffold_nodes(&target, Path::new(""), &mut nodes)?;
+    if nodes.is_empty() {
+        bail!("'{}' is empty", url.path.display());
+    }
+    Ok(Spec { nodes, ..Default::default() })
+}
+
+// Every entry below `dir` as a node, files with their contents. Symbolic links
+// are refused: they could point anywhere on this machine, not just into the template.
+fn scaffold_nodes(dir: &Path, rel: &Path, out: &mut Vec<Node>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("Cannot read '{}'", dir.display()))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Cannot read '{}'", dir.display()))?;
+    entries.sort();
+
+    for entry in entries {
+        let Some(name) = entry.file_name() else {
+            continue;
+        };
+        if name == ".git" {
+            continue;
+        }
+        let path = rel.join(name);
+        let meta = fs::symlink_metadata(&entry).with_context(|| format!("Cannot read '{}'", entry.display()))?;
+        if meta.file_type().is_symlink() {
+            bail!("'{}' is a symbolic link, templates can only contain files and directories", path.display());
+        }
+        if meta.is_dir() {
+            out.push(Node::dir(&path));
+            scaffold_nodes(&entry, &path, out)?;
+        } else {
+            let content = fs::read(&entry).with_context(|| format!("Cannot read '{}'", entry.display()))?;
+            out.push(Node { content: Some(content), ..Node::file(path) });
+        }
+    }
+    Ok(())
+}
+
+// The extracted contents of the archive or repository, from the cache when
+// they've been extracted before. Archives count as changed when their size or
+// modification time does, repositories are cached per commit.
+fn fetch(url: &TemplateUrl) -> Result<PathBuf> {
+    let meta = fs::metadata(&url.path).with_context(|| format!("Cannot open '{}'", url.path.display()))?;
+    let source = fs::canonicalize(&url.path).unwrap_or_else(|_| url.path.clone());
+
+    if meta.is_dir() {
+        if !is_git_repo(&source) {
+            bail!("'{}' is neither an archive nor a git repository", url.path.display());
+        }
+        let commit = resolve_commit(&source, url.reference.as_deref())?;
+        return cached(&("git", &source, &commit), |dir| export_commit(&source, &commit, dir));
+    }
+
+    if url.reference.is_some() {
+        bail!("'{}' is not a git repository, '#ref' only applies to those", url.path.display());
+    }
+    let Some(format) = ArchiveFormat::from_path(&url.path) else {
+        bail!("'{}' is neither a .tar, .tar.gz, .tgz or .zip archive nor a git repository", url.path.display());
+    };
+    let modified = meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok());
+    cached(&("archive", &source, meta.len(), modified), |dir| extract(&source, format, dir))
+}
+
+// Extracts into the cache directory for `key` unless that exists already.
+// Extraction goes to a temporary directory first so an interrupted one isn't used.
+fn cached(key: &impl Hash, extract: impl FnOnce(&Path) -> Result<()>) -> Result<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    let dir = cache_dir().join(format!("{:016x}", hasher.finish()));
+    if dir.is_dir() {
+        return Ok(dir);
+    }
+
+    let partial = dir.with_extension(format!("partial-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&partial);
+    fs::create_dir_all(&partial).with_context(|| format!("Failed to create directory '{}'", partial.display()))?;
+    if let Err(e) = extract(&partial) {
+        let _ = fs::remove_dir_all(&partial);
+        return Err(e);
+    }
+    if let Err(e) = fs::rename(&partial, &dir) {
+        let _ = fs::remove_dir_all(&partial);
+        // somebody else finished the same extraction first
+        if !dir.is_dir() {
+            return Err(e).with_context(|| format!("Failed to move '{}' into place", partial.display()));
+        }
+    }
+    Ok(dir)
+}
+
+// $XDG_CACHE_HOME/treegen/sources, ~/.cache/treegen/sources
+pub fn cache_dir() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(dirs::cache_dir)
+        .unwrap_or_else(env::temp_dir)
+        .join("treegen")
+        .join("sources")
+}
+
+fn extract(archive: &Path, format: ArchiveFormat, dir: &Path) -> Result<()> {
+    let file = File::open(archive).with_context(|| format!("Cannot open '{}'", archive.display()))?;
+    let unpacked = match format {
+        ArchiveFormat::Tar => tar::Archive::new(file).unpack(dir),
+        ArchiveFormat::TarGz => tar::Archive::new(GzDecoder::new(file)).unpack(dir),
+        ArchiveFormat::Zip => zip::ZipArchive::new(file).and_then(|mut zip| zip.extract(dir)).map_err(Into::into),
+    };
+    unpacked.with_context(|| format!("Failed to extract '{}'", archive.display()))
+}
+
+// the commit a branch, tag or commit id (HEAD without one) points to
+fn resolve_commit(repo: &Path, reference: Option<&str>) -> Result<String> {
+    let reference = reference.unwrap_or("HEAD");
+    if reference.starts_with('-') {
+        bail!("'{}' is not a valid git reference", reference);
+    }
+    let output = git(repo)
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{}^{{commit}}", reference))
+        .output()
+        .with_context(|| "Failed to run git".to_string())?;
+    if !output.status.success() {
+        bail!("'{}' has no branch, tag or commit '{}'", repo.display(), reference);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// the top of a repository (bare or not), not just some directory inside one
+fn is_git_repo(dir: &Path) -> bool {
+    let Ok(output) = git(dir).args(["rev-parse", "--absolute-git-dir"]).stderr(Stdio::null()).output() else {
+        return false;
+    };
+    let git_dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    output.status.success() && (git_dir == dir || git_dir == dir.join(".git"))
+}
+
+// the files of `commit`, through `git archive` so bare repositories work too
+fn export_commit(repo: &Path, commit: &str, dir: &Path) -> Result<()> {
+    let mut child = git(repo)
+        .args(["archive", "--format=tar", commit])
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| "Failed to run git".to_string())?;
+    let unpacked = match child.stdout.take() {
+        Some(stdout) => tar::Archive::new(stdout).unpack(dir),
+        None => Ok(()),
+    };
+    let status = child.wait().with_context(|| "Failed to run git".to_string())?;
+    if !status.success() {
+        bail!("git archive of '{}' at {} failed with {}", repo.display(), commit, status);
+    }
+    unpacked.with_context(|| format!("Failed to extract '{}' at {}", repo.display(), commit))
+}
+
+fn git(repo: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo).stdin(Stdio::null());
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_urls() {
+        let url = TemplateUrl::parse("file:///srv/scaffolds.git//rust/cli#v2").unwrap();
+        assert_eq!(url.path, PathBuf::from("/srv/scaffolds.git"));
+        assert_eq!(url.subdir, Some(PathBuf::from("rust/cli")));
+        assert_eq!(url.reference.as_deref(), Some("v2"));
+
+        let url = TemplateUrl::parse("file:///srv/rust.tar.gz").unwrap();
+        assert_eq!(url.path, PathBuf::from("/srv/rust.tar.gz"));
+        assert_eq!((url.subdir, url.reference), (None, None));
+
+        assert!(TemplateUrl::parse("file:///srv/a.tar//../etc").is_err());
+    }
+
+    #[test]
+    fn scaffold_reads_files_and_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+
+        let mut nodes = Vec::new();
+        scaffold_nodes(dir.path(), Path::new(""), &mut nodes).unwrap();
+        let paths: Vec<_> = nodes.iter().map(|n| n.path.clone()).collect();
+        assert_eq!(paths, [PathBuf::from("src"), PathBuf::from("src/main.rs")]);
+        assert_eq!(nodes[1].content.as_deref(), Some(&b"fn main() {}\n"[..]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scaffold_refuses_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        symlink("/etc/hostname", dir.path().join("leak.txt")).unwrap();
+        let mut nodes = Vec::new();
+        assert!(scaffold_nodes(dir.path(), Path::new(""), &mut nodes).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        symlink("/", dir.path().join("root")).unwrap();
+        let mut nodes = Vec::new();
+        assert!(scaffold_nodes(dir.path(), Path::new(""), &mut nodes).is_err());
+        assert!(nodes.is_empty());
+    }
+}
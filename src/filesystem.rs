@@ -0,0 +1,492 @@
+use ignore::WalkBuilder;
+use ignore::overrides::Override;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::{Context, Result};
+use crate::node::NodeKind;
+
+// What is at a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    // sockets, devices and the like
+    Other,
+}
+
+impl EntryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntryKind::File => "file",
+            EntryKind::Dir => "directory",
+            EntryKind::Other => "other",
+        }
+    }
+
+    // what a structure would declare it as, None for anything else
+    pub fn node_kind(&self) -> Option<NodeKind> {
+        match self {
+            EntryKind::File => Some(NodeKind::File),
+            EntryKind::Dir => Some(NodeKind::Dir),
+            EntryKind::Other => None,
+        }
+    }
+}
+
+// Everything creation, conflict detection and checking need from a file system.
+// Methods take &self so one backend can be shared with a Ctrl-C handler;
+// backends that keep state use interior mutability.
+pub trait FileSystem: Send + Sync {
+    // what is at `path`, following symlinks; None if there is nothing
+    fn kind(&self, path: &Path) -> Option<EntryKind>;
+
+    fn is_symlink(&self, path: &Path) -> bool;
+
+    // the entries directly inside `dir`, sorted
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+
+    // `path`'s parent has to exist
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    // creates or replaces a file
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    // only empty directories
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.kind(path).is_some()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.kind(path) == Some(EntryKind::Dir)
+    }
+
+    // Everything below `root`, depth first in name order, leaving out what
+    // `overrides` ignores (and the contents of ignored directories)
+    fn walk(&self, root: &Path, overrides: &Override) -> Result<Vec<PathBuf>> {
+        let mut found = Vec::new();
+        let entries = self.read_dir(root).with_context(|| format!("Cannot read '{}'", root.display()))?;
+        for entry in entries {
+            let is_dir = self.is_dir(&entry);
+            if overrides.matched(&entry, is_dir).is_ignore() {
+                continue;
+            }
+            found.push(entry.clone());
+            if is_dir && !self.is_symlink(&entry) {
+                found.extend(self.walk(&entry, overrides)?);
+            }
+        }
+        Ok(found)
+    }
+}
+
+
+// The real file system
+pub struct DiskFs;
+
+impl FileSystem for DiskFs {
+    fn kind(&self, path: &Path) -> Option<EntryKind> {
+        let meta = fs::metadata(path).ok()?;
+        Some(if meta.is_dir() {
+            EntryKind::Dir
+        } else if meta.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        })
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        path.is_symlink()
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut entries = fs::read_dir(dir)?.map(|e| e.map(|e| e.path())).collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        Ok(entries)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir(path)
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        fs::write(path, content)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    // .gitignore'd entries are left out too
+    fn walk(&self, root: &Path, overrides: &Override) -> Result<Vec<PathBuf>> {
+        let walker = WalkBuilder::new(root)
+            .hidden(false)
+            .require_git(false)
+            .overrides(overrides.clone())
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+        let mut found = Vec::new();
+        for entry in walker {
+            let entry = entry?;
+            if entry.depth() > 0 {
+                found.push(entry.into_path());
+            }
+        }
+        Ok(found)
+    }
+}
+
+
+// A file system that only exists in memory, for tests and previews. The
+// current directory is its root; paths are compared with `.` components dropped.
+#[derive(Default)]
+pub struct MemoryFs {
+    // None is a directory, Some the content of a file
+    entries: Mutex<BTreeMap<PathBuf, Option<Vec<u8>>>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        MemoryFs::default()
+    }
+
+    // the content of a file, None if there is no file at `path`
+    pub fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        self.lock().get(&key(path)).cloned().flatten()
+    }
+
+    // every entry, sorted
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.lock().keys().cloned().collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<PathBuf, Option<Vec<u8>>>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // the parent of `path` has to be a directory for it to be created
+    fn check_parent(&self, key: &Path) -> io::Result<()> {
+        match key.parent() {
+            Some(parent) if !self.is_dir(parent) => Err(not_found(parent)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn kind(&self, path: &Path) -> Option<EntryKind> {
+        let key = key(path);
+        if key.as_os_str().is_empty() {
+            return Some(EntryKind::Dir);
+        }
+        self.lock().get(&key).map(|e| if e.is_some() { EntryKind::File } else { EntryKind::Dir })
+    }
+
+    fn is_symlink(&self, _path: &Path) -> bool {
+        false
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        if !self.is_dir(dir) {
+            return Err(not_found(dir));
+        }
+        let parent = key(dir);
+        let entries = self.lock();
+        Ok(entries
+            .keys()
+            .filter(|k| k.parent() == Some(parent.as_path()))
+            .filter_map(|k| k.file_name())
+            .map(|name| dir.join(name))
+            .collect())
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let key = key(path);
+        if self.exists(&key) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("'{}' already exists", path.display())));
+        }
+        self.check_parent(&key)?;
+        self.lock().insert(key, None);
+        Ok(())
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        let key = key(path);
+        if self.is_dir(&key) {
+            return Err(io::Error::other(format!("'{}' is a directory", path.display())));
+        }
+        self.check_parent(&key)?;
+        self.lock().insert(key, Some(content.to_vec()));
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let key = key(path);
+        if self.kind(&key) != Some(EntryKind::File) {
+            return Err(not_found(path));
+        }
+        self.lock().remove(&key);
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        if !self.read_dir(path)?.is_empty() {
+            return Err(io::Error::other(format!("'{}' is not empty", path.display())));
+        }
+        self.lock().remove(&key(path));
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        let key = key(path);
+        if !self.is_dir(&key) {
+            return Err(not_found(path));
+        }
+        self.lock().retain(|k, _| !k.starts_with(&key));
+        Ok(())
+    }
+
+    // moves the entry and everything inside it; an existing file at `to` is replaced
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from_key, to_key) = (key(from), key(to));
+        if !self.exists(&from_key) {
+            return Err(not_found(from));
+        }
+        if self.is_dir(&to_key) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("'{}' already exists", to.display())));
+        }
+        self.check_parent(&to_key)?;
+
+        let mut entries = self.lock();
+        let moved: Vec<PathBuf> = entries.keys().filter(|k| k.starts_with(&from_key)).cloned().collect();
+        for old in moved {
+            if let Some(entry) = entries.remove(&old) {
+                let rest = old.strip_prefix(&from_key).unwrap_or(Path::new(""));
+                entries.insert(to_key.join(rest), entry);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn key(path: &Path) -> PathBuf {
+    path.components().filter(|c| !matches!(c, Component::CurDir)).collect()
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("'{}' doesn't exist", path.display()))
+}
+
+
+// A change made through a RecordingFs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    CreateDir(PathBuf),
+    Write { path: PathBuf, len: usize },
+    RemoveFile(PathBuf),
+    RemoveDir(PathBuf),
+    RemoveDirAll(PathBuf),
+    Rename { from: PathBuf, to: PathBuf },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::CreateDir(path) => write!(f, "create dir {}", path.display()),
+            Operation::Write { path, len } => write!(f, "write {} ({} bytes)", path.display(), len),
+            Operation::RemoveFile(path) => write!(f, "remove file {}", path.display()),
+            Operation::RemoveDir(path) => write!(f, "remove dir {}", path.display()),
+            Operation::RemoveDirAll(path) => write!(f, "remove dir and contents {}", path.display()),
+            Operation::Rename { from, to } => write!(f, "rename {} -> {}", from.display(), to.display()),
+        }
+    }
+}
+
+// Passes everything on to another backend and keeps a log of the changes
+// that went through, in order. Over a MemoryFs it shows what a run would do.
+pub struct RecordingFs<F> {
+    inner: F,
+    log: Mutex<Vec<Operation>>,
+}
+
+impl<F: FileSystem> RecordingFs<F> {
+    pub fn new(inner: F) -> Self {
+        RecordingFs { inner, log: Mutex::new(Vec::new()) }
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    // the changes made so far
+    pub fn operations(&self) -> Vec<Operation> {
+        self.log.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn record(&self, result: io::Result<()>, op: impl FnOnce() -> Operation) -> io::Result<()> {
+        if result.is_ok() {
+            self.log.lock().unwrap_or_else(|e| e.into_inner()).push(op());
+        }
+        result
+    }
+}
+
+impl<F: FileSystem> FileSystem for RecordingFs<F> {
+    fn kind(&self, path: &Path) -> Option<EntryKind> {
+        self.inner.kind(path)
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        self.inner.read_dir(dir)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.record(self.inner.create_dir(path), || Operation::CreateDir(path.to_path_buf()))
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        self.record(self.inner.write(path, content), || Operation::Write {
+            path: path.to_path_buf(),
+            len: content.len(),
+        })
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.record(self.inner.remove_file(path), || Operation::RemoveFile(path.to_path_buf()))
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.record(self.inner.remove_dir(path), || Operation::RemoveDir(path.to_path_buf()))
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.record(self.inner.remove_dir_all(path), || Operation::RemoveDirAll(path.to_path_buf()))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.record(self.inner.rename(from, to), || Operation::Rename {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        })
+    }
+
+    fn walk(&self, root: &Path, overrides: &Override) -> Result<Vec<PathBuf>> {
+        self.inner.walk(root, overrides)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ignore::overrides::OverrideBuilder;
+
+    // the same changes on any backend, returning what each step did and what is left
+    fn exercise(fs: &dyn FileSystem, base: &Path) -> Vec<String> {
+        let p = |rel: &str| base.join(rel);
+        let mut log = Vec::new();
+        let step = |what: &str, result: io::Result<()>| format!("{} {}", what, result.is_ok());
+
+        log.push(step("mkdir src", fs.create_dir(&p("src"))));
+        log.push(step("mkdir src/bin", fs.create_dir(&p("src/bin"))));
+        log.push(step("mkdir again", fs.create_dir(&p("src"))));
+        log.push(step("mkdir without parent", fs.create_dir(&p("a/b"))));
+        log.push(step("write lib", fs.write(&p("src/lib.rs"), b"lib")));
+        log.push(step("write main", fs.write(&p("src/bin/main.rs"), b"main")));
+        log.push(step("write readme", fs.write(&p("README.md"), b"readme")));
+        log.push(step("write debug.log", fs.write(&p("debug.log"), b"")));
+        log.push(step("write over dir", fs.write(&p("src"), b"")));
+
+        log.push(step("rename dir", fs.rename(&p("src/bin"), &p("tools"))));
+        log.push(step("rename over file", fs.rename(&p("README.md"), &p("src/lib.rs"))));
+        log.push(step("rename onto dir", fs.rename(&p("debug.log"), &p("tools"))));
+        log.push(step("rename missing", fs.rename(&p("nothing"), &p("else"))));
+
+        log.push(step("remove_file dir", fs.remove_file(&p("tools"))));
+        log.push(step("remove_dir full", fs.remove_dir(&p("tools"))));
+        log.push(step("remove_file missing", fs.remove_file(&p("README.md"))));
+        log.push(step("mkdir empty", fs.create_dir(&p("empty"))));
+        log.push(step("remove_dir empty", fs.remove_dir(&p("empty"))));
+
+        let mut overrides = OverrideBuilder::new(base);
+        overrides.add("!*.log").unwrap();
+        for path in fs.walk(base, &overrides.build().unwrap()).unwrap() {
+            let kind = fs.kind(&path).map(|k| k.as_str()).unwrap_or("none");
+            log.push(format!("{} {}", path.strip_prefix(base).unwrap().display(), kind));
+        }
+        log.push(step("remove_dir_all", fs.remove_dir_all(&p("tools"))));
+        log.push(format!("tools left {}", fs.exists(&p("tools/main.rs"))));
+        log
+    }
+
+    #[test]
+    fn memory_fs_behaves_like_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let on_disk = exercise(&DiskFs, dir.path());
+        let memory = MemoryFs::new();
+        assert_eq!(exercise(&memory, Path::new("")), on_disk);
+
+        assert!(on_disk.contains(&"tools/main.rs file".to_string()), "{:?}", on_disk);
+        assert_eq!(fs::read(dir.path().join("src/lib.rs")).unwrap(), b"readme");
+        assert_eq!(memory.read(Path::new("src/lib.rs")).as_deref(), Some("readme".as_bytes()));
+        assert_eq!(memory.read(Path::new("./src/lib.rs")), memory.read(Path::new("src/lib.rs")));
+    }
+
+    #[test]
+    fn recording_fs_logs_changes_in_order() {
+        let fs = RecordingFs::new(MemoryFs::new());
+        let root = Path::new("recorded-only");
+        fs.create_dir(root).unwrap();
+        fs.write(&root.join("a.txt"), b"abc").unwrap();
+        fs.rename(&root.join("a.txt"), &root.join("b.txt")).unwrap();
+        assert!(fs.remove_file(&root.join("a.txt")).is_err());
+        fs.remove_file(&root.join("b.txt")).unwrap();
+        fs.create_dir(&root.join("sub")).unwrap();
+        fs.remove_dir(&root.join("sub")).unwrap();
+        fs.remove_dir_all(root).unwrap();
+
+        assert_eq!(
+            fs.operations(),
+            [
+                Operation::CreateDir(root.into()),
+                Operation::Write { path: root.join("a.txt"), len: 3 },
+                Operation::Rename { from: root.join("a.txt"), to: root.join("b.txt") },
+                Operation::RemoveFile(root.join("b.txt")),
+                Operation::CreateDir(root.join("sub")),
+                Operation::RemoveDir(root.join("sub")),
+                Operation::RemoveDirAll(root.into()),
+            ]
+        );
+        // reads go straight through and nothing reached the disk
+        assert!(fs.inner().paths().is_empty());
+        assert!(!DiskFs.exists(root));
+    }
+}
@@ -0,0 +1,292 @@
+use crate::error::{Context, Error, Result, bail};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::node::{Node, NodeKind};
+use crate::markdown;
+use crate::parse::{ParseOptions, expand_name, parse_structure, parse_structure_file};
+use crate::spec::Spec;
+use crate::warning::Warning;
+
+// extensions of the nested-map formats, in template lookup order after .txt
+pub const MAP_EXTENSIONS: &[&str] = &["yaml", "yml", "json", "toml"];
+
+// Reads a structure in whichever format its extension says. Markdown documents
+// contribute one fenced block (see markdown::extract_block, `block` picks which),
+// anything that isn't YAML, JSON or TOML is the indented text format.
+pub fn parse_spec_file(path: &Path, block: Option<&str>, opts: &ParseOptions) -> Result<Spec> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    if ext == "md" || ext == "markdown" {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Cannot read file '{}'", path.display()))?;
+        let picked = markdown::extract_block(&content, block)
+            .with_context(|| format!("In '{}'", path.display()))?;
+        let mut spec = parse_structure(&picked.body, path, picked.first_line, opts)?;
+        if block.is_none() && picked.count > 1 {
+            spec.warnings.insert(0, Warning::FirstBlock { path: path.to_path_buf(), count: picked.count });
+        }
+        return Ok(spec);
+    }
+    if !MAP_EXTENSIONS.contains(&ext.as_str()) {
+        return parse_structure_file(path, opts);
+    }
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Cannot read file '{}'", path.display()))?;
+    let value = match ext.as_str() {
+        "json" => serde_json::from_str(&content).map_err(|e| {
+            let message = e.to_string();
+            syntax_error(path, e.line(), e.column(), &message)
+        })?,
+        "toml" => toml::from_str(&content).map_err(|e| {
+            let (line, column) = e.span().map(|span| line_column(&content, span.start)).unwrap_or((1, 1));
+            syntax_error(path, line, column, e.message())
+        })?,
+        _ => yaml_to_json(serde_yaml::from_str(&content).map_err(|e| {
+            let (line, column) = e.location().map(|at| (at.line(), at.column())).unwrap_or((1, 1));
+            syntax_error(path, line, column, &e.to_string())
+        })?),
+    };
+
+    let mut nodes = Vec::new();
+    walk(Path::new(""), &value, &mut nodes)?;
+    if nodes.is_empty() {
+        bail!("Structure file '{}' is empty.", path.display());
+    }
+    Ok(Spec { nodes, ..Default::default() })
+}
+
+// Maps are directories, strings are file contents, null is an empty file
+// (or directory, for keys ending in '/'), and lists name the entries of a directory:
+//
+//   src:
+//     main.rs: "fn main() {}"
+//     handlers: [a.rs, b.rs, tests/]
+//   README.md:
+//
+// Keys and list names are brace-expanded, quoted and escaped like the names of
+// text structure files.
+fn walk(dir: &Path, value: &Value, out: &mut Vec<Node>) -> Result<()> {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                entry(dir, key, value, out)?;
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                match item {
+                    Value::String(name) => {
+                        let (names, is_dir) = names(dir, name)?;
+                        for name in names {
+                            let path = dir.join(name);
+                            out.push(if is_dir { Node::dir(path) } else { Node::file(path) });
+                        }
+                    }
+                    Value::Object(_) => walk(dir, item, out)?,
+                    other => bail!(
+                        "'{}': list entries must be names or maps, found {}",
+                        dir.display(),
+                        describe(other)
+                    ),
+                }
+            }
+        }
+        Value::Null => {}
+        other => bail!("expected a map at the top level, found {}", describe(other)),
+    }
+    Ok(())
+}
+
+fn entry(dir: &Path, key: &str, value: &Value, out: &mut Vec<Node>) -> Result<()> {
+    let (names, declared_dir) = names(dir, key)?;
+    for name in names {
+        let path: PathBuf = dir.join(name);
+        match value {
+            Value::Object(_) | Value::Array(_) => {
+                out.push(Node::dir(&path));
+                walk(&path, value, out)?;
+            }
+            Value::Null if declared_dir => out.push(Node::dir(&path)),
+            Value::Null => out.push(Node::file(&path)),
+            Value::String(_) if declared_dir => {
+                bail!("'{}': directory can't have content", path.display());
+            }
+            Value::String(text) => out.push(Node {
+                path,
+                kind: NodeKind::File,
+                content: Some(text.clone().into_bytes()),
+            }),
+            other => bail!(
+                "'{}': expected a map, list, string or null, found {}",
+                path.display(),
+                describe(other)
+            ),
+        }
+    }
+    Ok(())
+}
+
+// the names a key or list entry expands to, and whether it ends in '/'
+fn names(dir: &Path, key: &str) -> Result<(Vec<String>, bool)> {
+    let (names, is_dir) = expand_name(key).with_context(|| format!("'{}': invalid entry name '{}'", dir.display(), key))?;
+    if names.iter().any(String::is_empty) {
+        bail!("'{}': empty entry name", dir.display());
+    }
+    Ok((names, is_dir))
+}
+
+// serde_json and serde_yaml append " at line L column C" to their messages,
+// which is already in front of it
+fn syntax_error(path: &Path, line: usize, column: usize, message: &str) -> Error {
+    let message = message.rsplit_once(" at line ").map_or(message, |(message, _)| message);
+    Error::Syntax { path: path.to_path_buf(), line, column, message: message.to_string() }
+}
+
+// 1-based line and column of a byte offset
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+fn describe(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "a list",
+        Value::Object(_) => "a map",
+    }
+}
+
+// YAML allows non-string keys like `2024:`, JSON doesn't, so convert by hand
+fn yaml_to_json(value: serde_yaml::Value) -> Value {
+    use serde_yaml::Value as Y;
+    match value {
+        Y::Null => Value::Null,
+        Y::Bool(b) => Value::Bool(b),
+        Y::Number(n) => serde_json::to_value(&n).unwrap_or(Value::Null),
+        Y::String(s) => Value::String(s),
+        Y::Sequence(items) => Value::Array(items.into_iter().map(yaml_to_json).collect()),
+        Y::Mapping(map) => {
+            let mut out = Map::new();
+            for (k, v) in map {
+                let key = match k {
+                    Y::String(s) => s,
+                    Y::Number(n) => n.to_string(),
+                    Y::Bool(b) => b.to_string(),
+                    other => serde_yaml::to_string(&other).unwrap_or_default().trim().to_string(),
+                };
+                out.insert(key, yaml_to_json(v));
+            }
+            Value::Object(out)
+        }
+        Y::Tagged(tagged) => yaml_to_json(tagged.value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str, text: &str) -> Result<Vec<Node>> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, text).unwrap();
+        Ok(parse_spec_file(&path, None, &ParseOptions::default())?.nodes)
+    }
+
+    const YAML: &str = r#"
+src:
+  main.rs: "fn main() {}\n"
+  "{api,db}":
+    mod.rs:
+  handlers: [a.rs, "b c.rs", tests/]
+docs/:
+"\\{raw\\}.txt":
+"#;
+
+    const JSON: &str = r#"{
+  "src": {
+    "main.rs": "fn main() {}\n",
+    "{api,db}": { "mod.rs": null },
+    "handlers": ["a.rs", "\"b c.rs\"", "tests/"]
+  },
+  "docs/": null,
+  "\"{raw}.txt\"": null
+}"#;
+
+    const TOML: &str = r#"
+"docs/" = {}
+'\{raw\}.txt' = ""
+
+[src]
+"main.rs" = """
+fn main() {}
+"""
+handlers = ["a.rs", "b\\ c.rs", "tests/"]
+
+[src."{api,db}"]
+"mod.rs" = ""
+"#;
+
+    fn expected() -> Vec<(String, NodeKind, Option<&'static str>)> {
+        let file = |p: &str, c| (p.to_string(), NodeKind::File, c);
+        let dir = |p: &str| (p.to_string(), NodeKind::Dir, None);
+        vec![
+            dir("src"),
+            file("src/main.rs", Some("fn main() {}\n")),
+            dir("src/api"),
+            file("src/api/mod.rs", None),
+            dir("src/db"),
+            file("src/db/mod.rs", None),
+            dir("src/handlers"),
+            file("src/handlers/a.rs", None),
+            file("src/handlers/b c.rs", None),
+            dir("src/handlers/tests"),
+            dir("docs"),
+            file("{raw}.txt", None),
+        ]
+    }
+
+    // path order, so the formats' own key orders don't matter; TOML has no null
+    // and an empty table can't be a file, so "" and {} stand in for it
+    fn normalized(mut nodes: Vec<Node>) -> Vec<(String, NodeKind, Option<String>)> {
+        nodes.sort_by(|a, b| a.path.cmp(&b.path));
+        nodes
+            .into_iter()
+            .map(|n| {
+                let content = n.content.map(|c| String::from_utf8(c).unwrap()).filter(|c| !c.is_empty());
+                (n.path.to_string_lossy().into_owned(), n.kind, content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn yaml_json_and_toml_read_the_same() {
+        let mut want: Vec<_> = expected().into_iter().map(|(p, k, c)| (p, k, c.map(String::from))).collect();
+        want.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(normalized(parse("s.yaml", YAML).unwrap()), want);
+        assert_eq!(normalized(parse("s.json", JSON).unwrap()), want);
+        assert_eq!(normalized(parse("s.toml", TOML).unwrap()), want);
+    }
+
+    #[test]
+    fn values_that_arent_entries_are_rejected() {
+        for (name, text) in [("a.json", "42"), ("b.json", "\"src\""), ("c.yaml", "true"), ("d.yaml", "src:\n  a.txt: 3\n")] {
+            let err = parse(name, text).unwrap_err();
+            assert!(err.to_string().contains("expected a map"), "{}: {}", name, err);
+        }
+        let err = parse("e.json", r#"{"src": [1]}"#).unwrap_err();
+        assert!(err.to_string().contains("list entries must be names or maps"), "{}", err);
+        let err = parse("f.yaml", "docs/: text\n").unwrap_err();
+        assert!(err.to_string().contains("can't have content"), "{}", err);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::error::{Context, Result, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::formats::parse_spec_file;
+use crate::node::{Node, NodeKind};
+use crate::parse::ParseOptions;
+use crate::spec::{Directive, Header, Spec};
+
+// Builds the complete entry list of a structure that extends or includes other
+// templates. Bases named by `extends:` come first, then the file's own entries
+// and `!include`s in order. An entry declared again by a later source replaces
+// the earlier one, and `!exclude` drops whatever came before it.
+//
+// Names containing a '/' are files relative to the including one, anything else
+// is a template name that `find_template` looks up.
+pub fn resolve(spec: Spec, path: &Path, find_template: &dyn Fn(&str) -> Option<PathBuf>, opts: &ParseOptions) -> Result<Spec> {
+    let mut chain = vec![identity(path)];
+    resolve_in(spec, path, find_template, opts, &mut chain)
+}
+
+fn resolve_in(
+    spec: Spec,
+    path: &Path,
+    find_template: &dyn Fn(&str) -> Option<PathBuf>,
+    opts: &ParseOptions,
+    chain: &mut Vec<PathBuf>,
+) -> Result<Spec> {
+    let mut header = Header::default();
+    let mut entries = Entries::default();
+    let mut warnings = spec.warnings;
+
+    for base in &spec.header.extends {
+        let inherited = load(base, path, find_template, opts, chain)?;
+        warnings.extend(inherited.warnings);
+        merge_header(&mut header, inherited.header);
+        entries.add_inherited(inherited.nodes, Path::new(""));
+    }
+
+    let mut directives = spec.directives.into_iter().peekable();
+    let mut nodes = spec.nodes.into_iter().enumerate().peekable();
+    loop {
+        let position = nodes.peek().map(|(i, _)| *i).unwrap_or(usize::MAX);
+        if let Some((_, directive)) = directives.next_if(|(at, _)| *at <= position) {
+            match directive {
+                Directive::Include { name, at } => {
+                    let included = load(&name, path, find_template, opts, chain)?;
+                    warnings.extend(included.warnings);
+                    merge_header(&mut header, included.header);
+                    entries.add_inherited(included.nodes, &at);
+                }
+                Directive::Exclude(excluded) => entries.remove(&excluded),
+            }
+            continue;
+        }
+        match nodes.next() {
+            Some((_, node)) => entries.add_own(node),
+            None => break,
+        }
+    }
+
+    // the file's own header goes last so its description and defaults win
+    merge_header(&mut header, spec.header);
+    let nodes = entries.nodes.into_iter().map(|(node, _)| node).collect();
+    Ok(Spec { header, nodes, directives: Vec::new(), warnings })
+}
+
+fn load(
+    name: &str,
+    from: &Path,
+    find_template: &dyn Fn(&str) -> Option<PathBuf>,
+    opts: &ParseOptions,
+    chain: &mut Vec<PathBuf>,
+) -> Result<Spec> {
+    let file = if name.contains('/') {
+        from.parent().unwrap_or_else(|| Path::new(".")).join(name)
+    } else {
+        match find_template(name) {
+            Some(file) => file,
+            None => bail!("'{}' includes template '{}', which isn't installed", from.display(), name),
+        }
+    };
+    if !file.exists() {
+        bail!("'{}' includes '{}', which doesn't exist ({})", from.display(), name, file.display());
+    }
+
+    let id = identity(&file);
+    if let Some(start) = chain.iter().position(|p| *p == id) {
+        let cycle: Vec<String> = chain[start..].iter().chain([&id]).map(|p| p.display().to_string()).collect();
+        bail!("Template include cycle: {}", cycle.join(" -> "));
+    }
+
+    let spec = parse_spec_file(&file, None, opts)
+        .with_context(|| format!("In '{}' (included from '{}')", file.display(), from.display()))?;
+    chain.push(id);
+    let resolved = resolve_in(spec, &file, find_template, opts, chain);
+    chain.pop();
+    resolved
+}
+
+// the same file reached through different paths has to count as one for cycles
+fn identity(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// later descriptions and variable defaults take over earlier ones
+fn merge_header(into: &mut Header, from: Header) {
+    if from.description.is_some() {
+        into.description = from.description;
+    }
+    for (name, default) in from.vars {
+        match into.vars.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, existing)) => {
+                if default.is_some() {
+                    *existing = default;
+                }
+            }
+            None => into.vars.push((name, default)),
+        }
+    }
+}
+
+// Entries in declaration order, each marked with whether it was inherited.
+// Anything declared again replaces an inherited entry; the file's own duplicates
+// are kept so conflicting declarations are still reported.
+#[derive(Default)]
+struct Entries {
+    nodes: Vec<(Node, bool)>,
+}
+
+impl Entries {
+    fn add_inherited(&mut self, nodes: Vec<Node>, at: &Path) {
+        for node in nodes {
+            let path = at.join(&node.path);
+            self.add(Node { path, ..node }, true);
+        }
+    }
+
+    fn add_own(&mut self, node: Node) {
+        self.add(node, false);
+    }
+
+    fn add(&mut self, node: Node, inherited: bool) {
+        let replaces = |(n, from_base): &(Node, bool)| n.path == node.path && (*from_base || inherited);
+        let Some(i) = self.nodes.iter().position(replaces) else {
+            self.nodes.push((node, inherited));
+            return;
+        };
+        // a directory replaced by a file takes its contents with it
+        if self.nodes[i].0.kind == NodeKind::Dir && node.kind == NodeKind::File {
+            self.nodes.retain(|(n, _)| n.path == node.path || !n.path.starts_with(&node.path));
+        }
+        if let Some(i) = self.nodes.iter().position(replaces) {
+            self.nodes[i] = (node, inherited);
+        }
+    }
+
+    fn remove(&mut self, path: &Path) {
+        self.nodes.retain(|(n, _)| !n.path.starts_with(path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // writes the files into a temporary template directory, then resolves `main`
+    fn resolve_files(files: &[(&str, &str)]) -> Result<Spec> {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, text) in files {
+            fs::write(dir.path().join(name), text).unwrap();
+        }
+        let main = dir.path().join("main.txt");
+        let templates = dir.path().to_path_buf();
+        let find = move |name: &str| Some(templates.join(format!("{}.txt", name))).filter(|p| p.is_file());
+        let opts = ParseOptions::default();
+        resolve(parse_spec_file(&main, None, &opts)?, &main, &find, &opts)
+    }
+
+    fn paths(spec: &Spec) -> Vec<String> {
+        spec.nodes.iter().map(|n| n.path.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn extends_and_includes() {
+        let spec = resolve_files(&[
+            ("base.txt", "---\ndescription: base\nvar name: base\n---\nREADME.md\nsrc/\n    lib.rs\n"),
+            ("ci.txt", "workflow.yml\n"),
+            ("main.txt", "---\nextends: base\nvar name: app\n---\n.github/\n    !include ci\nsrc/\n    lib.rs <<EOF\n    mine\n    EOF\n"),
+        ])
+        .unwrap();
+        assert_eq!(paths(&spec), ["README.md", "src", "src/lib.rs", ".github", ".github/workflow.yml"]);
+        assert_eq!(spec.nodes[2].content.as_deref(), Some("mine\n".as_bytes()));
+        assert_eq!(spec.header.description.as_deref(), Some("base"));
+        assert_eq!(spec.header.vars, [("name".to_string(), Some("app".to_string()))]);
+    }
+
+    #[test]
+    fn excludes_drop_what_came_before() {
+        let spec = resolve_files(&[
+            ("base.txt", "docs/\n    guide.md\nsrc/\n    main.rs\n"),
+            ("main.txt", "---\nextends: base\n---\n!exclude docs\ntests/\n!exclude tests\ndocs/\n"),
+        ])
+        .unwrap();
+        assert_eq!(paths(&spec), ["src", "src/main.rs", "docs"]);
+    }
+
+    #[test]
+    fn relative_includes() {
+        let spec = resolve_files(&[("part.txt", "a.txt\n"), ("main.txt", "sub/\n    !include ./part.txt\n")]).unwrap();
+        assert_eq!(paths(&spec), ["sub", "sub/a.txt"]);
+    }
+
+    #[test]
+    fn missing_templates_and_cycles_are_errors() {
+        let err = resolve_files(&[("main.txt", "!include nowhere\n")]).unwrap_err();
+        assert!(err.to_string().contains("isn't installed"), "{}", err);
+
+        let err = resolve_files(&[
+            ("a.txt", "---\nextends: b\n---\na.txt\n"),
+            ("b.txt", "!include a\nb.txt\n"),
+            ("main.txt", "!include a\n"),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("Template include cycle"), "{}", err);
+
+        let err = resolve_files(&[("main.txt", "!include main\n")]).unwrap_err();
+        assert!(err.to_string().contains("cycle"), "{}", err);
+    }
+}
@@ -0,0 +1,48 @@
+// treegen as a library: read a structure from text, a structure file or a
+// template, collect it under an output directory and create it.
+//
+//     let spec = treegen::parse_structure("src/\n    main.rs\nCargo.toml\n", Path::new("layout.txt"), 1, &ParseOptions::default())?;
+//     let mut all_paths = BTreeMap::new();
+//     treegen::collect_groups(Path::new("out"), &[spec.nodes], &mut all_paths)?;
+//     let resolver = Resolver::new(Some(ConflictPolicy::Merge), true, false);
+//     Executor::new(Arc::new(DiskFs), resolver, true).run(&all_paths)?;
+//
+// Errors are treegen::Error. Warnings come back in Spec::warnings, and what a
+// run does about existing entries as executor::Events; nothing in here exits
+// the process or prints unless asked to (the interactive prompts, print_tree...).
+
+pub mod archive;
+pub mod check;
+pub mod conflict;
+pub mod defaults;
+pub mod error;
+pub mod escape;
+pub mod executor;
+pub mod expand;
+pub mod external;
+pub mod filesystem;
+pub mod formats;
+pub mod include;
+pub mod markdown;
+pub mod node;
+pub mod parse;
+pub mod plan;
+pub mod snapshot;
+pub mod spec;
+pub mod templates;
+pub mod transaction;
+pub mod tree;
+pub mod vars;
+pub mod warning;
+
+pub use conflict::{ConflictPolicy, Resolver};
+pub use error::{Error, Result};
+pub use executor::Executor;
+pub use filesystem::{DiskFs, FileSystem, MemoryFs, RecordingFs};
+pub use formats::parse_spec_file;
+pub use node::{Node, NodeKind};
+pub use parse::{ParseOptions, TabPolicy, parse_groups, parse_structure, parse_structure_file};
+pub use plan::{Plan, collect_groups};
+pub use spec::{Directive, Header, Spec};
+pub use templates::Store;
+pub use warning::Warning;
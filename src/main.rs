@@ -1,89 +1,324 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use colored::*;
-use std::collections::BTreeSet;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
-
+use std::sync::Arc;
+
+use treegen::{archive, check, defaults, formats, markdown, node, snapshot, templates, vars};
+use treegen::conflict::{ConflictPolicy, Resolver};
+use treegen::executor::{self, Event, Executor};
+use treegen::external::{self, TemplateUrl};
+use treegen::filesystem::DiskFs;
+use treegen::node::{Node, NodeKind};
+use treegen::plan::{Plan, collect_groups};
+use treegen::formats::parse_spec_file;
+use treegen::parse::{ParseOptions, TabPolicy, parse_groups};
+use treegen::spec::{Header, Spec};
+use treegen::templates::Store;
+use treegen::vars::Vars;
+use treegen::warning::Warning;
+use treegen::tree::{Sort, TreeStyle, print_tree};
+use treegen::transaction::Journal;
 
 #[derive(Parser, Debug)]
 #[command(name = "treegen",version = "0.1.0",author = "JoeChala", about = "Generate directory and file structures easily")]
-
-
 struct Args {
-    //File and directory structure
-    paths: Vec<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    source: SourceArgs,
 
     //Output directory
     #[arg(short, long,default_value = ".", help = "Base output directory")]
     output: PathBuf,
     
-    //preview the tree before creating
-    #[arg(long)]
+    //preview the tree and what would change, then stop
+    #[arg(long, help = "Show the structure and what would change, without creating anything")]
     dry : bool,
-    
+
+    //plain ascii connectors for terminals without unicode
+    #[arg(long, help = "Draw the preview with ASCII characters only")]
+    ascii: bool,
+
+    //preview ordering
+    #[arg(long, value_enum, default_value = "dirs-first", help = "Order of entries in the preview")]
+    sort: Sort,
+
+    //preview, then ask before creating
+    #[arg(long, conflicts_with = "dry", help = "Show what would change and ask before creating")]
+    confirm: bool,
+
+    //what to do with entries that already exist
+    #[arg(long, value_enum, value_name = "POLICY", help = "How to handle existing entries instead of asking")]
+    on_conflict: Option<ConflictPolicy>,
+
+    //never prompt, for scripts and CI
+    #[arg(short = 'y', long, visible_alias = "non-interactive", help = "Never prompt; existing entries are merged unless --on-conflict says otherwise")]
+    yes: bool,
+
+    //allow overwrite to delete directories that have content
+    #[arg(long, help = "Let overwrite replace existing non-empty directories (their contents are deleted)")]
+    force_replace_dirs: bool,
+
+    //keep whatever was created if something fails
+    #[arg(long, help = "Don't undo already created entries when a later one fails")]
+    no_rollback: bool,
+
+    //write an archive instead of creating anything
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["output", "confirm"], help = "Write the structure to a .tar, .tar.gz/.tgz or .zip archive instead of the disk")]
+    archive: Option<PathBuf>,
+
+    //extensionless names that should be treated as files
+    #[arg(long = "file-name", value_name = "NAME", value_delimiter = ',', help = "Extensionless file name to recognise, e.g. Procfile (repeatable)")]
+    file_names: Vec<String>,
+
+    //searched before every other template directory
+    #[arg(long = "template-dir", value_name = "DIR", global = true, help = "Look for templates here first (repeatable); new templates are saved in the first one")]
+    template_dirs: Vec<PathBuf>,
+
+}
+
+//where the structure comes from, shared by every command that reads one
+#[derive(clap::Args, Debug)]
+struct SourceArgs {
+    //File and directory structure
+    paths: Vec<String>,
+
     //load tree from text file
     #[arg(long)]
     from: Option<PathBuf>,
     
+    //which fenced block of a Markdown --from file to use
+    #[arg(long, value_name = "N|HEADING", requires = "from", help = "Pick a ```treegen block of a Markdown file by number (from 1) or heading")]
+    block: Option<String>,
+
     //load tree from a saved template
     #[arg(long)]
     template: Option<String>,
 
     //create default structre for a language
-    #[arg(long)]
+    #[arg(long, value_name = "NAME", help = "Start from a built-in structure, e.g. rust-bin, python, go (`--default list` shows them all)")]
     default: Option<String>,
 
+    //values for {{placeholders}}
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = vars::parse_var, help = "Set a template variable (repeatable)")]
+    vars: Vec<(String, String)>,
+
+    //file with key=value lines for {{placeholders}}
+    #[arg(long, value_name = "FILE", help = "Read template variables from a key=value file")]
+    vars_file: Option<PathBuf>,
+
+    //make sense of sloppy indentation instead of stopping at it
+    #[arg(long, help = "Accept uneven indentation and children of files (made directories) in structure files")]
+    lenient: bool,
+
+    //what tabs in the indentation of structure files mean
+    #[arg(long, value_enum, default_value_t = TabPolicy::Strict, help = "Tabs in structure files: strict (tabs or spaces per file), expand (mix freely), reject")]
+    tabs: TabPolicy,
+
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=64), help = "Columns per tab stop in structure files (1 to 64)")]
+    tab_width: usize,
 }
-fn main() -> Result<()> {
-    let args = Args::parse();
-    if args.paths.is_empty() && args.from.is_none() && args.template.is_none() && args.default.is_none() {
-        eprintln!("{} No input provided. Use arguements, --from, --template, or --default.","Error:".red());
-        std::process::exit(1);
-    }
-    let mut all_paths = BTreeSet::new();
 
-    //args priority, template > from > default > args
-    if let Some(template_name) = args.template {
-        let template_path = get_template_path(&template_name);
+#[derive(Subcommand, Debug)]
+enum Command {
+    //turn an existing directory into a template
+    #[command(about = "Save an existing directory as a template")]
+    Save(SaveArgs),
 
-        if !template_path.exists() {
-            eprintln!("{} template not found {}", "Error:".red(),template_path.display());
-            std::process::exit(1);
-        }
-        let lines = parse_structure_file(&template_path)
-            .with_context(|| format!("Failed to read template file: {}", template_path.display()))?;
+    //verify a directory against a structure
+    #[command(about = "Check that a directory matches a structure")]
+    Check(CheckArgs),
 
-        collect_groups(&args.output, &[lines], &mut all_paths)?;
-    } else if let Some(file) = args.from {
-        let lines = parse_structure_file(&file)
-            .with_context(|| format!("Failed to read structure file : {}",file.display()))?;
+    //look after the saved templates
+    #[command(about = "List, show, add, remove, edit, rename or validate templates")]
+    Template(TemplateArgs),
+}
 
-        collect_groups(&args.output, &[lines], &mut all_paths)?;
-    } else if let Some(lang) = args.default {
-        let structure = get_default(&lang);
-        if structure.is_empty() {
-            eprintln!("{} unknown default template '{}'", "Error:".red(), lang);
-            std::process::exit(1);
-        }
-        collect_groups(&args.output, &[structure], &mut all_paths)?;
-    } else {
-        let groups = parse_groups(args.paths);
-        collect_groups(&args.output, &groups, &mut all_paths)?;
+#[derive(clap::Args, Debug)]
+struct SaveArgs {
+    //template name
+    name: String,
+
+    //directory to snapshot
+    #[arg(long = "from-dir", visible_alias = "snapshot", value_name = "DIR", default_value = ".", help = "Directory to snapshot")]
+    from_dir: PathBuf,
+
+    //write somewhere else than the template directory
+    #[arg(long, value_name = "FILE", help = "Write the structure to this file instead of the template directory")]
+    to: Option<PathBuf>,
+
+    #[arg(long, help = "Store the contents of text files too")]
+    with_content: bool,
+
+    #[arg(long, value_name = "N", help = "Only descend N levels")]
+    max_depth: Option<usize>,
+
+    #[arg(long, value_name = "GLOB", help = "Leave out matching entries (repeatable)")]
+    exclude: Vec<String>,
+
+    #[arg(long, help = "Description written to the template header")]
+    description: Option<String>,
+
+    #[arg(long, help = "Replace an existing template of the same name")]
+    force: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct CheckArgs {
+    #[command(flatten)]
+    source: SourceArgs,
+
+    //directory to check
+    #[arg(short, long, default_value = ".", help = "Directory to check")]
+    output: PathBuf,
+
+    #[arg(long, help = "Also report entries that aren't in the structure")]
+    strict: bool,
+
+    #[arg(long, value_name = "GLOB", help = "Never report matching entries as extra (repeatable)")]
+    exclude: Vec<String>,
+
+    #[arg(long, value_enum, default_value = "text", help = "Report format")]
+    format: check::Format,
+}
+
+#[derive(clap::Args, Debug)]
+struct TemplateArgs {
+    #[command(subcommand)]
+    action: TemplateCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum TemplateCommand {
+    #[command(about = "List installed templates with their descriptions")]
+    List,
+
+    #[command(about = "Show where a template name resolves to, and the search order")]
+    Which { name: String },
+
+    #[command(about = "Draw a template's tree, as --dry does")]
+    Show {
+        name: String,
+
+        #[arg(long, help = "Draw the tree with ASCII characters only")]
+        ascii: bool,
+
+        #[arg(long, value_enum, default_value = "dirs-first", help = "Order of entries in the tree")]
+        sort: Sort,
+
+        #[arg(long, help = "Print the template file as it is instead")]
+        raw: bool,
+    },
+
+    #[command(about = "Install a structure file as a template")]
+    Add {
+        name: String,
+
+        //structure file in any format --from reads
+        file: PathBuf,
+
+        #[arg(long, value_name = "N|HEADING", help = "Block to take from a Markdown file")]
+        block: Option<String>,
+
+        #[arg(long, help = "Replace an existing template of the same name")]
+        force: bool,
+    },
+
+    #[command(about = "Delete a template")]
+    Remove { name: String },
+
+    #[command(about = "Open a template in $VISUAL or $EDITOR, creating it if needed")]
+    Edit { name: String },
+
+    #[command(about = "Give a template another name")]
+    Rename {
+        from: String,
+        to: String,
+
+        #[arg(long, help = "Replace an existing template of the new name")]
+        force: bool,
+    },
+
+    #[command(about = "Check that templates parse and their includes resolve (all of them by default)")]
+    Validate { names: Vec<String> },
+}
+
+fn main() -> Result<()> {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    check_command_line(&matches, &args);
+    let store = Store::new(&args.template_dirs);
+
+    match args.command {
+        Some(Command::Save(save)) => return save_template(save, &store),
+        Some(Command::Check(check)) => return run_check(check, &store),
+        Some(Command::Template(template)) => return run_template(template.action, &store),
+        None => {}
     }
 
+    if args.source.default.as_deref() == Some("list") {
+        return list_defaults(&store);
+    }
+
+    let interactive = !args.yes && std::io::stdin().is_terminal();
+    let groups = load_groups(args.source, interactive, &store)?;
+
+    let mut all_paths = BTreeMap::new();
+    collect_groups(&args.output, &groups, &mut all_paths)?;
+
     if all_paths.is_empty() {
         eprintln!("{} No valid paths to generate.", "Error:".yellow());
         std::process::exit(1);
     }
 
-    if args.dry {
+    for (path, node) in &all_paths {
+        if node.kind == NodeKind::File && node::is_ambiguous_file(path, &args.file_names) {
+            eprintln!(
+                "{} '{}' has no extension and will be created as a file. Add a trailing '/' to make it a directory.",
+                "Warning:".yellow(),
+                path.display()
+            );
+        }
+    }
+
+    let mut resolver = Resolver::new(args.on_conflict, args.yes, args.force_replace_dirs);
+
+    // Nothing touches the disk but the archive itself
+    if let Some(path) = &args.archive {
+        if args.dry {
+            println!("\nArchive contents preview:\n");
+            print_tree(&args.output, &all_paths, TreeStyle { ascii: args.ascii, sort: args.sort });
+            println!("\n(No archive written yet)\n");
+            return Ok(());
+        }
+        if !archive_may_be_written(path, &mut resolver)? {
+            return Ok(());
+        }
+        archive::write_archive(path, &args.output, &all_paths)?;
+        println!("Wrote {} entries to {}", all_paths.len(), path.display());
+        return Ok(());
+    }
+
+    if args.dry || args.confirm {
         println!("\nProject structure preview:\n");
-        print_tree(&args.output, &all_paths);
+        let style = TreeStyle { ascii: args.ascii, sort: args.sort };
+        print_tree(&args.output, &all_paths, style);
+        println!();
+        Plan::build(&DiskFs, &all_paths, resolver.policy(), args.force_replace_dirs).print(&args.output);
         println!("\n(No files created yet)\n");
+    }
+    if args.dry {
+        return Ok(());
+    }
 
+    if args.confirm && !args.yes {
         // Ask for user confirmation
         print!("Would you like to create this structure? (y/n): ");
         std::io::stdout().flush()?;
@@ -99,11 +334,42 @@ fn main() -> Result<()> {
         println!("Proceeding to create directories and files...\n");
     } 
 
-    // Try to create files and dirs
-    for path in &all_paths {
-        if let Err(e) = create_path(path) {
-            eprintln!("{} {},failed to create {}", "Error:".red(),e, path.display());
+    let mut executor = Executor::new(Arc::new(DiskFs), resolver, !args.no_rollback).on_event(report_event);
+
+    // Undo this run's changes on Ctrl-C
+    if !args.no_rollback {
+        let journal = executor.journal();
+        ctrlc::set_handler(move || {
+            let mut journal = executor::lock(&journal);
+            eprintln!("\n{} Interrupted, rolling back...", "Error:".red());
+            report_rollback(journal.rollback());
+            std::process::exit(130);
+        })
+        .context("Failed to install Ctrl-C handler")?;
+    }
+
+    if let Err(e) = executor.run(&all_paths) {
+        let cancelled = e.is_cancelled();
+        if cancelled {
+            println!("Operation cancelled.");
+        } else {
+            eprintln!("{} {:#}", "Error:".red(), anyhow::Error::from(e));
         }
+
+        if args.no_rollback {
+            eprintln!(
+                "{} --no-rollback given, leaving {} created entries in place.",
+                "Warning:".yellow(),
+                executor.created_count()
+            );
+            for error in executor.commit() {
+                eprintln!("{} {}", "Warning:".yellow(), error);
+            }
+        } else {
+            eprintln!("Rolling back {} created entries...", executor.created_count());
+            report_rollback(executor.rollback());
+        }
+        std::process::exit(if cancelled { 0 } else { 1 });
     }
 
     println!("Structure created successfully!!");
@@ -111,237 +377,518 @@ fn main() -> Result<()> {
 }
 
 
+// Subcommands win over paths, so `treegen --dry template list` runs the
+// subcommand. The options for creating a structure don't apply to one, and a
+// path spelled like a subcommand is most likely a misplaced one.
+fn check_command_line(matches: &ArgMatches, args: &Args) {
+    let mut cmd = Args::command();
+    cmd.build();
+    if let Some((name, _)) = matches.subcommand() {
+        let given = cmd.get_arguments().find(|a| {
+            a.get_id() != "template_dirs" && matches.value_source(a.get_id().as_str()) == Some(ValueSource::CommandLine)
+        });
+        if let Some(arg) = given {
+            let msg = format!("'{}' doesn't apply to the '{}' subcommand (its own options go after its name)", arg, name);
+            cmd.error(clap::error::ErrorKind::ArgumentConflict, msg).exit();
+        }
+        return;
+    }
 
-fn get_template_path(name: &str) -> PathBuf {
-    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-    home.join(".config/treegen/templates").join(format!("{}.txt",name))
+    let names: Vec<String> = cmd.get_subcommands().map(|c| c.get_name().to_string()).collect();
+    if let Some(path) = args.source.paths.iter().find(|p| names.contains(p)) {
+        let msg = format!("'{0}' is a subcommand, it has to come before any path (write './{0}' to create a file named '{0}')", path);
+        cmd.error(clap::error::ErrorKind::ArgumentConflict, msg).exit();
+    }
 }
 
 
-fn parse_structure_file(path: &Path) -> Result<Vec<String>> {
-    let content = fs::read_to_string(path)
-        .with_context(|| format!("Cannot read file '{}'", path.display()))?;
+// An existing archive goes through the conflict policy like any other entry,
+// except that there is nothing to merge into
+fn archive_may_be_written(path: &Path, resolver: &mut Resolver) -> Result<bool> {
+    if !path.exists() {
+        return Ok(true);
+    }
+    match resolver.resolve(path)? {
+        ConflictPolicy::Overwrite => Ok(true),
+        ConflictPolicy::Skip => {
+            report_event(Event::Skipped(path.to_path_buf()));
+            Ok(false)
+        }
+        ConflictPolicy::Backup => {
+            let to = Journal::new(Arc::new(DiskFs), false).backup(path)?;
+            report_event(Event::BackedUp { path: path.to_path_buf(), to });
+            Ok(true)
+        }
+        ConflictPolicy::Error => anyhow::bail!("'{}' already exists", path.display()),
+        ConflictPolicy::Merge => anyhow::bail!(
+            "'{}' already exists and an archive can't be merged into, use --on-conflict overwrite, backup or skip",
+            path.display()
+        ),
+    }
+}
 
-    let mut lines = Vec::new();
-    let mut dir_stack: Vec<String> = Vec::new();
-    let mut detected_indent = None;
 
-    for raw_line in content.lines() {
-        let trimmed = raw_line.trim_end();
-        if trimmed.trim().is_empty() {
-            continue;
+fn report_event(event: Event) {
+    const SHOWN: usize = 20;
+    match event {
+        Event::Skipped(path) => println!("Skipped '{}'", path.display()),
+        Event::KeptExisting(path) => println!("Kept existing '{}'", path.display()),
+        Event::BackedUp { path, to } => println!("Backed up '{}' to '{}'", path.display(), to.display()),
+        Event::Replacing { dir, lost } => {
+            println!("{} Replacing '{}' will delete {} entries:", "Warning:".yellow(), dir.display(), lost.len());
+            for entry in lost.iter().take(SHOWN) {
+                println!("  {}", entry.display().to_string().red());
+            }
+            if lost.len() > SHOWN {
+                println!("  ... and {} more", lost.len() - SHOWN);
+            }
         }
+        Event::Warning(message) => eprintln!("{} {}", "Warning:".yellow(), message),
+    }
+}
+
 
-        // Replace tabs with spaces for consistent handling
-        let line = raw_line.replace('\t', "    ");
+fn print_warnings(warnings: &[Warning]) {
+    for warning in warnings {
+        eprintln!("{} {}", "Warning:".yellow(), warning);
+    }
+}
+
+
+fn report_rollback(errors: Vec<String>) {
+    if errors.is_empty() {
+        eprintln!("Rolled back, nothing from this run was left behind.");
+        return;
+    }
+    eprintln!("{} Rollback could not undo everything:", "Warning:".yellow());
+    for e in errors {
+        eprintln!("  {}", e);
+    }
+}
+
+
+
+// the single group of a structure read from a file, after showing its warnings
+fn take_spec(spec: Spec) -> (Vec<Vec<Node>>, Header) {
+    print_warnings(&spec.warnings);
+    (vec![spec.nodes], spec.header)
+}
+
+
+// Reads the structure from whichever source was given, with variables filled in
+fn load_groups(source: SourceArgs, interactive: bool, store: &Store) -> Result<Vec<Vec<Node>>> {
+    if source.paths.is_empty() && source.from.is_none() && source.template.is_none() && source.default.is_none() {
+        eprintln!("{} No input provided. Use arguements, --from, --template, or --default.","Error:".red());
+        std::process::exit(1);
+    }
+
+    let parse_opts = ParseOptions { lenient: source.lenient, tabs: source.tabs, tab_width: NonZeroUsize::new(source.tab_width).expect("--tab-width is at least 1") };
+
+    //args priority, template > from > default > args
+    let (mut groups, header) = if let Some(url) = source.template.as_deref().filter(|t| external::is_url(t)) {
+        let spec = external::load(&TemplateUrl::parse(url)?, &|name| store.get_template_path(name), &parse_opts)
+            .with_context(|| format!("Failed to read template {}", url))?;
+
+        take_spec(spec)
+    } else if let Some(template_name) = source.template {
+        let Some(template_path) = store.get_template_path(&template_name) else {
+            eprintln!("{} template '{}' not found in {}", "Error:".red(), template_name, store.searched());
+            std::process::exit(1);
+        };
+        let spec = store.load(&template_path, None, &parse_opts)
+            .with_context(|| format!("Failed to read template file: {}", template_path.display()))?;
 
-        // Detect indentation width dynamically from first indented line
-        if detected_indent.is_none() {
-            if let Some(first_space) = line.find(|c: char| !c.is_whitespace()) {
-                if first_space > 0 {
-                    detected_indent = Some(first_space);
-                }
+        take_spec(spec)
+    } else if let Some(file) = source.from {
+        let spec = store.load(&file, source.block.as_deref(), &parse_opts)
+            .with_context(|| format!("Failed to read structure file : {}",file.display()))?;
+
+        take_spec(spec)
+    } else if let Some(name) = source.default {
+        // an installed template of the same name (or of the default's main name) replaces the built-in one
+        let builtin = defaults::find(&name);
+        let installed = std::iter::once(name.as_str())
+            .chain(builtin.map(|b| b.name))
+            .find_map(|n| store.get_template_path(n));
+        let spec = match (installed, builtin) {
+            (Some(path), _) => store.load(&path, None, &parse_opts)
+                .with_context(|| format!("Failed to read template file: {}", path.display()))?,
+            (None, Some(builtin)) => builtin.spec(&parse_opts)?,
+            (None, None) => {
+                eprintln!("{} unknown default template '{}', see --default list", "Error:".red(), name);
+                std::process::exit(1);
             }
-        }
+        };
 
-        let indent_width = detected_indent.unwrap_or(4);
-        let indent_level = line.chars().take_while(|c| *c == ' ').count() / indent_width;
+        take_spec(spec)
+    } else {
+        (parse_groups(source.paths)?, Header::default())
+    };
+
+    // Fill in {{placeholders}} before anything is looked at on disk
+    let file_vars = match &source.vars_file {
+        Some(path) => vars::load_vars_file(path)?,
+        None => Vars::new(),
+    };
+    let values = vars::resolve(&header, &groups, file_vars, &source.vars, interactive)?;
+    vars::apply(&mut groups, &values);
+
+    Ok(groups)
+}
 
-        let name = line.trim();
 
-        while dir_stack.len() > indent_level {
-            dir_stack.pop();
-        }
+fn run_check(check: CheckArgs, store: &Store) -> Result<()> {
+    let groups = load_groups(check.source, std::io::stdin().is_terminal(), store)?;
 
-        let mut full_path = dir_stack.join("/");
-        if !full_path.is_empty() {
-            full_path.push('/');
-        }
-        full_path.push_str(name.trim_end_matches('/'));
+    let mut all_paths = BTreeMap::new();
+    collect_groups(&check.output, &groups, &mut all_paths)?;
 
-        lines.push(full_path.clone());
+    let findings = check::check(&DiskFs, &check.output, &all_paths, check.strict, &check.exclude)?;
+    check::report(&check.output, all_paths.len(), &findings, check.format);
 
-        if name.ends_with('/') {
-            dir_stack.push(name.trim_end_matches('/').to_string());
-        }
+    if !findings.is_empty() {
+        std::process::exit(1);
     }
+    Ok(())
+}
 
-    if lines.is_empty() {
-        eprintln!(
-            "{} Structure file '{}' is empty.",
-            "Warning:".yellow(),
-            path.display()
-        );
+
+fn save_template(save: SaveArgs, store: &Store) -> Result<()> {
+    templates::check_name(&save.name)?;
+    let opts = snapshot::SnapshotOptions {
+        with_content: save.with_content,
+        max_depth: save.max_depth,
+        exclude: &save.exclude,
+        description: save.description.as_deref(),
+    };
+    let structure = snapshot::snapshot(&save.from_dir, &opts)?;
+    print_warnings(&structure.warnings);
+
+    let target = match save.to {
+        Some(to) => to,
+        None => store.write_dir()?.join(format!("{}.txt", save.name)),
+    };
+    if target.exists() && !save.force {
+        eprintln!("{} '{}' already exists, use --force to replace it.", "Error:".red(), target.display());
         std::process::exit(1);
     }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory '{}'", parent.display()))?;
+    }
+    fs::write(&target, structure.text)
+        .with_context(|| format!("Failed to write '{}'", target.display()))?;
 
-    Ok(lines)
+    println!("Saved '{}' as {}", save.from_dir.display(), target.display());
+    Ok(())
 }
 
 
-fn get_default(lang: &str) -> Vec<String> {
-    match lang {
-        "py" | "python" => vec![
-            "src/__init__.py".into(),
-            "src/main.py".into(),
-            ".gitignore".into(),
-            "requirements.txt".into(),
-            "README.md".into(),
-        ],
-        "rs" | "rust" => vec![
-            "src/main.rs".into(),
-            "Cargo.toml".into(),
-            ".gitignore".into(),
-            "README.md".into(),
-        ],
-        "web" | "js" | "ts" => vec![
-            "src/index.js".into(),
-            "src/style.css".into(),
-            "public/index.html".into(),
-            ".gitignore".into(),
-            "package.json".into(),
-            "README.md".into(),
-        ],
-        _ => vec![],
+fn run_template(action: TemplateCommand, store: &Store) -> Result<()> {
+    match action {
+        TemplateCommand::List => list_templates(store),
+        TemplateCommand::Show { name, ascii, sort, raw } => show_template(store, &name, TreeStyle { ascii, sort }, raw),
+        TemplateCommand::Which { name } => which_template(store, &name),
+        TemplateCommand::Add { name, file, block, force } => add_template(store, &name, &file, block.as_deref(), force),
+        TemplateCommand::Remove { name } => remove_template(store, &name),
+        TemplateCommand::Edit { name } => edit_template(store, &name),
+        TemplateCommand::Rename { from, to, force } => rename_template(store, &from, &to, force),
+        TemplateCommand::Validate { names } => validate_templates(store, names),
     }
 }
 
 
-fn parse_groups(tokens: Vec<String>) -> Vec<Vec<String>> {
-    let mut groups = Vec::new();
-    let mut current = Vec::new();
+// path of an installed template, or an error naming where it was looked for
+fn existing_template(store: &Store, name: &str) -> Result<PathBuf> {
+    templates::check_name(name)?;
+    store
+        .get_template_path(name)
+        .with_context(|| format!("template '{}' not found in {}", name, store.searched()))
+}
+
 
-    for token in tokens {
-        if token == ":" {
-            if !current.is_empty() {
-                groups.push(current);
-                current = Vec::new();
+fn list_templates(store: &Store) -> Result<()> {
+    let all = store.all()?;
+    if all.is_empty() {
+        println!("No templates in {}", store.searched());
+        return Ok(());
+    }
+
+    let width = all.iter().map(|t| t.name.len()).max().unwrap_or(0);
+    for (dir, origin) in store.dirs() {
+        let here: Vec<&templates::Template> = all.iter().filter(|t| t.path.parent() == Some(dir.as_path())).collect();
+        if here.is_empty() {
+            continue;
+        }
+        println!("Templates in {} ({}):", dir.display(), origin.as_str());
+        for template in here {
+            let description = match parse_spec_file(&template.path, None, &ParseOptions::default()) {
+                Ok(spec) => spec.header.description.unwrap_or_default().normal(),
+                Err(e) => format!("(invalid: {:#})", e).red(),
+            };
+            if template.shadowed {
+                println!("  {:width$}  {}", template.name.dimmed(), "(shadowed)".dimmed(), width = width);
+            } else {
+                println!("  {:width$}  {}", template.name.green().bold(), description, width = width);
             }
-        } else {
-            current.push(token);
         }
     }
-    if !current.is_empty() {
-        groups.push(current);
-    }
-    groups
+    Ok(())
 }
 
 
-fn collect_groups(base: &Path, groups: &[Vec<String>], all_paths: &mut BTreeSet<PathBuf>) -> Result<()> {
-    for group in groups {
-        for token in group {
-            let clean = token.trim();
-            let clean = clean.trim_start_matches("./");
+fn show_template(store: &Store, name: &str, style: TreeStyle, raw: bool) -> Result<()> {
+    let path = existing_template(store, name)?;
+    if raw {
+        print!("{}", fs::read_to_string(&path).with_context(|| format!("Cannot read '{}'", path.display()))?);
+        return Ok(());
+    }
+
+    let spec = store
+        .load(&path, None, &ParseOptions::default())
+        .with_context(|| format!("Failed to read template file: {}", path.display()))?;
+    let base = Path::new(".");
+    let mut all_paths = BTreeMap::new();
+    collect_groups(base, &[spec.nodes], &mut all_paths)?;
+
+    if let Some(description) = &spec.header.description {
+        println!("{}\n", description);
+    }
+    print_tree(base, &all_paths, style);
+    Ok(())
+}
 
-            // Construct full path relative to output directory
-            let path = base.join(clean);
 
-            all_paths.insert(path.clone());
+// where `name` resolves to, then every directory of the search path with the file
+// it holds for `name`, if any (marked shadowed unless it is the one that wins)
+fn which_template(store: &Store, name: &str) -> Result<()> {
+    let found = store.get_template_path(name);
+    match &found {
+        Some(path) => println!("{} -> {}", name, path.display().to_string().green()),
+        None => println!("{} {}", name, "is not installed".red()),
+    }
 
-            if let Some(parent) = path.parent() {
-                all_paths.insert(parent.to_path_buf());
+    println!("\nSearch order:");
+    for (i, (dir, origin)) in store.dirs().iter().enumerate() {
+        let line = format!("{:2}. {} ({})", i + 1, dir.display(), origin.as_str());
+        match templates::file_in(dir, name) {
+            Some(path) if Some(&path) == found.as_ref() => {
+                println!("{}  {}", line, path.file_name().unwrap_or_default().to_string_lossy().green());
             }
+            Some(path) => println!(
+                "{}  {}",
+                line,
+                format!("{} (shadowed)", path.file_name().unwrap_or_default().to_string_lossy()).dimmed()
+            ),
+            None => println!("{}", line),
         }
     }
 
+    if found.is_none() {
+        std::process::exit(1);
+    }
     Ok(())
 }
 
 
+fn add_template(store: &Store, name: &str, file: &Path, block: Option<&str>, force: bool) -> Result<()> {
+    templates::check_name(name)?;
+    let dir = store.write_dir()?;
+    let existing = templates::file_in(&dir, name);
+    if existing.is_some() && !force {
+        eprintln!("{} template '{}' already exists, use --force to replace it.", "Error:".red(), name);
+        std::process::exit(1);
+    }
+    let spec = store
+        .load(file, block, &ParseOptions::default())
+        .with_context(|| format!("'{}' is not a valid structure file", file.display()))?;
+    print_warnings(&spec.warnings);
+
+    let content = fs::read_to_string(file).with_context(|| format!("Cannot read '{}'", file.display()))?;
+    let ext = file.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).unwrap_or_default();
+    // Markdown documents contribute their structure block, kept as a text template
+    let (content, ext) = match ext.as_str() {
+        "md" | "markdown" => (markdown::extract_block(&content, block)?.body, "txt"),
+        e if formats::MAP_EXTENSIONS.contains(&e) => (content, e),
+        _ => (content, "txt"),
+    };
+
+    let target = dir.join(format!("{}.{}", name, ext));
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create directory '{}'", dir.display()))?;
+    fs::write(&target, content).with_context(|| format!("Failed to write '{}'", target.display()))?;
+    // a replaced template in another format would otherwise shadow or be shadowed by the new one
+    if let Some(existing) = existing
+        && existing != target
+    {
+        fs::remove_file(&existing).with_context(|| format!("Failed to remove '{}'", existing.display()))?;
+    }
 
-fn print_tree(base: &Path, paths: &BTreeSet<PathBuf>) {
-    println!("{}", "📦 Project Structure:".bold().cyan());
-    for path in paths {
-        let rel = match path.strip_prefix(base) {
-            Ok(p) if !p.as_os_str().is_empty() => p,
-            _ => continue,
-        };
-        let depth = rel.components().count();
-        let indent = "  ".repeat(depth - 1);
-        let name = rel.file_name().unwrap_or_default().to_string_lossy();
-        
-        let is_dotfile = name.starts_with('.');
-        let has_extension = path.extension().is_some();
-        let is_special_file = ["Dockerfile", "Makefile"].contains(&name.as_ref());
-
-        if !has_extension && (!is_dotfile || !is_special_file) {
-            // Folder
-            println!("{}📁 {}", indent, name.blue().bold());
-        } else {
-            // File
-            let emoji = match path.extension().and_then(|e| e.to_str()) {
-                Some("rs") => "🦀",
-                Some("py") => "🐍",
-                Some("js") | Some("ts") => "🧩",
-                Some("toml") => "📝",
-                Some("md") => "📘",
-                Some("html") => "🌐",
-                Some("css") => "🎨",
-                _ => "📄",
-            };
-            if is_dotfile || is_special_file {
-                println!("{}{} {}",indent,"📝",name.red());
-            }
-            println!("{}{} {}", indent, emoji, name.green());
+    println!("Added template '{}' as {}", name, target.display());
+    Ok(())
+}
+
+
+fn remove_template(store: &Store, name: &str) -> Result<()> {
+    let path = existing_template(store, name)?;
+    fs::remove_file(&path).with_context(|| format!("Failed to remove '{}'", path.display()))?;
+    println!("Removed template '{}' ({})", name, path.display());
+    if let Some(next) = store.get_template_path(name) {
+        println!("'{}' now resolves to {}", name, next.display());
+        return Ok(());
+    }
+    warn_dependents(store, name)
+}
+
+
+fn edit_template(store: &Store, name: &str) -> Result<()> {
+    templates::check_name(name)?;
+    let (path, created) = match store.get_template_path(name) {
+        Some(path) => (path, false),
+        None => (store.write_dir()?.join(format!("{}.txt", name)), true),
+    };
+    let stub = format!("# {}: one entry per line, directories end in '/'\n", name);
+    if created {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create directory '{}'", dir.display()))?;
+        }
+        fs::write(&path, &stub).with_context(|| format!("Failed to write '{}'", path.display()))?;
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // the variable may carry arguments, like "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Failed to start editor '{}'", editor))?;
+
+    // a new template that was left as it was isn't kept
+    if created && fs::read_to_string(&path).is_ok_and(|content| content == stub) {
+        fs::remove_file(&path).with_context(|| format!("Failed to remove '{}'", path.display()))?;
+        if status.success() {
+            println!("Template '{}' left empty, not saved.", name);
+            return Ok(());
         }
     }
+    if !status.success() {
+        anyhow::bail!("editor '{}' exited with {}", editor, status);
+    }
+
+    if let Err(e) = store.load(&path, None, &ParseOptions::default()) {
+        eprintln!("{} template '{}' doesn't parse: {:#}", "Warning:".yellow(), name, e);
+    }
+    Ok(())
 }
 
 
-fn create_path(path: &Path) -> Result<()> {
-    // If it already exists, ask what to do
-    if path.exists() {
-        let rel = path.display();
-        println!("Warning '{}' already exists.", rel);
-        print!("Do you want to overwrite (o), skip (s), or cancel (c)? [o/s/c]: ");
-        io::stdout().flush()?; // Make sure prompt shows
+// the renamed template stays in the directory it was found in
+fn rename_template(store: &Store, from: &str, to: &str, force: bool) -> Result<()> {
+    templates::check_name(to)?;
+    let source = existing_template(store, from)?;
+    let dir = source.parent().unwrap_or_else(|| Path::new("."));
+    let existing = templates::file_in(dir, to);
+    if existing.is_some() && !force {
+        eprintln!("{} template '{}' already exists, use --force to replace it.", "Error:".red(), to);
+        std::process::exit(1);
+    }
 
-        let mut answer = String::new();
-        io::stdin().read_line(&mut answer)?;
-        let choice = answer.trim().to_lowercase();
+    let ext = source.extension().and_then(|e| e.to_str()).unwrap_or("txt");
+    let target = dir.join(format!("{}.{}", to, ext));
+    if let Some(existing) = existing
+        && existing != target
+    {
+        fs::remove_file(&existing).with_context(|| format!("Failed to remove '{}'", existing.display()))?;
+    }
+    fs::rename(&source, &target)
+        .with_context(|| format!("Failed to rename '{}' to '{}'", source.display(), target.display()))?;
 
-        match choice.as_str() {
-            "s" | "skip" => {
-                println!("Skipped '{}'", rel);
-                return Ok(());
-            }
-            "c" | "cancel" => {
-                println!("Operation cancelled.");
-                std::process::exit(0);
-            }
-            "o" | "overwrite" | "" => {
-                // Continue to recreate the file/dir
-                if path.is_dir() {
-                    fs::remove_dir_all(path)
-                        .with_context(|| format!("Failed to remove existing directory '{}'", rel))?;
-                } else {
-                    fs::remove_file(path)
-                        .with_context(|| format!("Failed to remove existing file '{}'", rel))?;
-                }
-            }
-            _ => {
-                println!("Unknown option '{}', skipping '{}'.", choice, rel);
-                return Ok(());
-            }
-        }
+    println!("Renamed template '{}' to '{}'", from, to);
+    if store.get_template_path(from).is_some() {
+        return Ok(());
     }
+    warn_dependents(store, from)
+}
 
-    // Make sure parent directories exist
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .with_context(|| format!("Failed to create directory '{}'", parent.display()))?;
+
+// templates extending or including one that's gone would fail to load
+fn warn_dependents(store: &Store, name: &str) -> Result<()> {
+    let dependents = store.dependents(name)?;
+    if !dependents.is_empty() {
+        eprintln!(
+            "{} still referring to '{}': {}",
+            "Warning:".yellow(),
+            name,
+            dependents.join(", ")
+        );
     }
+    Ok(())
+}
+
 
-    // Create either a directory or file
-    if path.extension().is_some() {
-        fs::File::create(path)
-            .with_context(|| format!("Failed to create file '{}'", path.display()))?;
+fn validate_templates(store: &Store, names: Vec<String>) -> Result<()> {
+    let targets: Vec<(String, Option<PathBuf>)> = if names.is_empty() {
+        store.installed()?.into_iter().map(|t| (t.name, Some(t.path))).collect()
     } else {
-        fs::create_dir_all(path)
-            .with_context(|| format!("Failed to create directory '{}'", path.display()))?;
+        names.into_iter().map(|name| {
+            let path = store.get_template_path(&name);
+            (name, path)
+        }).collect()
+    };
+    if targets.is_empty() {
+        println!("No templates in {}", store.searched());
+        return Ok(());
     }
 
+    let mut invalid = 0;
+    for (name, path) in targets {
+        let result = match path {
+            Some(path) => store.load(&path, None, &ParseOptions::default()).and_then(|spec| {
+                let mut all_paths = BTreeMap::new();
+                collect_groups(Path::new("."), &[spec.nodes], &mut all_paths)?;
+                Ok(all_paths.len())
+            }),
+            None => Err(treegen::Error::Invalid("not found".to_string())),
+        };
+        match result {
+            Ok(count) => println!("  {:7} {} ({} entries)", "ok".green(), name, count),
+            Err(e) => {
+                invalid += 1;
+                println!("  {:7} {}: {:#}", "invalid".red(), name, e);
+            }
+        }
+    }
+
+    if invalid > 0 {
+        std::process::exit(1);
+    }
     Ok(())
 }
 
+
+// the built-in defaults, and which of them an installed template replaces
+fn list_defaults(store: &Store) -> Result<()> {
+    let width = defaults::BUILTINS.iter().map(|b| b.name.len()).max().unwrap_or(0);
+    println!("Built-in defaults (--default NAME):");
+    for builtin in defaults::BUILTINS {
+        let mut line = format!(
+            "  {}  {}",
+            format!("{:width$}", builtin.name, width = width).green().bold(),
+            builtin.description()?.unwrap_or_default()
+        );
+        if !builtin.aliases.is_empty() {
+            line.push_str(&format!(" {}", format!("(also {})", builtin.aliases.join(", ")).dimmed()));
+        }
+        println!("{}", line);
+        let names = std::iter::once(builtin.name).chain(builtin.aliases.iter().copied());
+        for name in names {
+            if let Some(path) = store.get_template_path(name) {
+                println!("  {:width$}  {}", "", format!("'{}' is replaced by {}", name, path.display()).yellow(), width = width);
+            }
+        }
+    }
+    Ok(())
+}
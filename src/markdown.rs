@@ -0,0 +1,165 @@
+use crate::error::{Result, bail};
+
+// info strings of the fenced blocks we pick up
+const TAGS: &[&str] = &["treegen", "tree"];
+
+#[derive(Debug)]
+pub struct Block {
+    // closest heading above the block, without the #s
+    pub heading: Option<String>,
+    pub body: String,
+    // line of the document the body starts on, counting from 1
+    pub first_line: usize,
+    // how many structure blocks the document has
+    pub count: usize,
+}
+
+// Finds the ```treegen / ```tree blocks of a Markdown document and returns
+// the one `select` names: a 1-based index or (part of) the heading above it.
+// Without a selection the first block is used.
+pub fn extract_block(markdown: &str, select: Option<&str>) -> Result<Block> {
+    let mut blocks = fenced_blocks(markdown);
+    if blocks.is_empty() {
+        bail!("no ```treegen or ```tree code block found");
+    }
+
+    let Some(select) = select else {
+        return Ok(blocks.into_iter().next().expect("checked above"));
+    };
+
+    if let Ok(index) = select.parse::<usize>() {
+        let count = blocks.len();
+        return match index.checked_sub(1).and_then(|i| blocks.into_iter().nth(i)) {
+            Some(block) => Ok(block),
+            None => bail!("there is no block {}, the document has {}", index, count),
+        };
+    }
+
+    let wanted = select.trim().to_lowercase();
+    let heading_of = |b: &Block| b.heading.as_deref().unwrap_or("").to_lowercase();
+    let found = blocks
+        .iter()
+        .position(|b| heading_of(b) == wanted)
+        .or_else(|| blocks.iter().position(|b| heading_of(b).contains(&wanted)));
+    match found {
+        Some(i) => Ok(blocks.swap_remove(i)),
+        None => bail!("no structure block under a heading matching '{}'", select),
+    }
+}
+
+fn fenced_blocks(markdown: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut heading = None;
+    let mut lines = markdown.lines().enumerate();
+
+    while let Some((line_no, line)) = lines.next() {
+        let trimmed = line.trim_start();
+        if let Some(text) = trimmed.strip_prefix('#') {
+            heading = Some(text.trim_start_matches('#').trim().to_string());
+            continue;
+        }
+
+        let Some((fence, info)) = open_fence(trimmed) else {
+            continue;
+        };
+        let tag = info.split_whitespace().next().unwrap_or("");
+        let wanted = TAGS.iter().any(|t| t.eq_ignore_ascii_case(tag));
+
+        // a block ends at a fence of the same character that's at least as long
+        let mut body = String::new();
+        for (_, line) in lines.by_ref() {
+            let t = line.trim();
+            if t.starts_with(&fence) && t.chars().all(|c| c == fence.chars().next().unwrap_or('`')) {
+                break;
+            }
+            body.push_str(line);
+            body.push('\n');
+        }
+        if wanted {
+            blocks.push(Block { heading: heading.clone(), body, first_line: line_no + 2, count: 0 });
+        }
+    }
+    let count = blocks.len();
+    for block in &mut blocks {
+        block.count = count;
+    }
+    blocks
+}
+
+// "```treegen" -> ("```", "treegen")
+fn open_fence(line: &str) -> Option<(String, &str)> {
+    let ch = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|c| *c == ch).count();
+    if len < 3 {
+        return None;
+    }
+    let fence = ch.to_string().repeat(len);
+    Some((fence, line[len..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "\
+# Project
+
+```rust
+fn not_this() {}
+```
+
+## Backend
+
+```treegen
+api/
+    main.rs
+```
+
+## Frontend
+
+~~~tree
+ui/
+~~~
+
+## Docs
+
+````Tree title=\"docs\"
+docs/
+    README.md <<EOF
+    ```sh
+    make
+    ```
+    EOF
+````
+";
+
+    #[test]
+    fn picks_tagged_blocks() {
+        let first = extract_block(DOC, None).unwrap();
+        assert_eq!(first.body, "api/\n    main.rs\n");
+        assert_eq!(first.heading.as_deref(), Some("Backend"));
+        assert_eq!(first.first_line, 10);
+        assert_eq!(first.count, 3);
+
+        assert_eq!(extract_block(DOC, Some("2")).unwrap().body, "ui/\n");
+        assert_eq!(extract_block(DOC, Some("front")).unwrap().body, "ui/\n");
+        assert!(extract_block(DOC, Some("4")).is_err());
+        assert!(extract_block(DOC, Some("0")).is_err());
+        assert!(extract_block(DOC, Some("nowhere")).is_err());
+    }
+
+    #[test]
+    fn untagged_blocks_dont_count() {
+        let err = extract_block("# Notes\n\n```\nsrc/\n```\n\n```text\na.txt\n```\n", None).unwrap_err();
+        assert!(err.to_string().contains("no ```treegen or ```tree code block"), "{}", err);
+    }
+
+    #[test]
+    fn longer_fences_hold_shorter_ones() {
+        let docs = extract_block(DOC, Some("docs")).unwrap();
+        assert_eq!(docs.body, "docs/\n    README.md <<EOF\n    ```sh\n    make\n    ```\n    EOF\n");
+        // a ~~~ block isn't closed by backticks
+        let tilde = extract_block("~~~treegen\na/\n```\n~~~\n", None).unwrap();
+        assert_eq!(tilde.body, "a/\n```\n");
+    }
+}
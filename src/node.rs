@@ -0,0 +1,85 @@
+use std::path::{Path, PathBuf};
+
+//extensionless names that are files, not folders
+pub const KNOWN_FILES: &[&str] = &[
+    "Makefile",
+    "makefile",
+    "GNUmakefile",
+    "Dockerfile",
+    "Containerfile",
+    "Jenkinsfile",
+    "Vagrantfile",
+    "Procfile",
+    "Gemfile",
+    "Rakefile",
+    "Brewfile",
+    "Justfile",
+    "justfile",
+    "LICENSE",
+    "LICENCE",
+    "COPYING",
+    "README",
+    "CHANGELOG",
+    "AUTHORS",
+    "CONTRIBUTORS",
+    "NOTICE",
+    "CODEOWNERS",
+    "VERSION",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NodeKind {
+    Dir,
+    File,
+}
+
+impl NodeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeKind::Dir => "directory",
+            NodeKind::File => "file",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub path: PathBuf,
+    pub kind: NodeKind,
+    // what a file starts out with, None leaves it empty
+    pub content: Option<Vec<u8>>,
+}
+
+impl Node {
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Node { path: path.into(), kind: NodeKind::File, content: None }
+    }
+
+    pub fn dir(path: impl Into<PathBuf>) -> Self {
+        Node { path: path.into(), kind: NodeKind::Dir, content: None }
+    }
+
+    // trailing '/' marks a directory, everything else is a file
+    pub fn from_token(token: &str) -> Self {
+        let clean = token.trim();
+        let clean = clean.trim_start_matches("./");
+        if clean.ends_with('/') {
+            Node::dir(clean.trim_end_matches('/'))
+        } else {
+            Node::file(clean)
+        }
+    }
+}
+
+// true when a file name gives no hint that it is a file:
+// no extension, not a dotfile and not in the known list
+pub fn is_ambiguous_file(path: &Path, known_files: &[String]) -> bool {
+    let name = match path.file_name() {
+        Some(n) => n.to_string_lossy(),
+        None => return false,
+    };
+    if name.starts_with('.') || path.extension().is_some() {
+        return false;
+    }
+    !KNOWN_FILES.contains(&name.as_ref()) && !known_files.iter().any(|k| k == name.as_ref())
+}
@@ -0,0 +1,839 @@
+use crate::error::{Context, Error, Result, bail};
+use clap::ValueEnum;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Component, Path, PathBuf};
+
+use crate::escape::{ends_in_quotes, quotes_to_escapes, strip_comment, unescape};
+use crate::expand::expand_braces;
+use crate::node::{Node, NodeKind};
+use crate::spec::{Directive, Header, Spec};
+use crate::vars::is_var_name;
+use crate::warning::Warning;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TabPolicy {
+    // tabs or spaces, but one of them per file
+    Strict,
+    // tabs and spaces may be mixed, tabs advance to the next tab stop
+    Expand,
+    // spaces only
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParseOptions {
+    // guess what sloppy indentation meant instead of stopping at it
+    pub lenient: bool,
+    pub tabs: TabPolicy,
+    // columns between tab stops, a 0 would leave tabs without a stop to advance to
+    pub tab_width: NonZeroUsize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions { lenient: false, tabs: TabPolicy::Strict, tab_width: NonZeroUsize::new(4).expect("4 isn't 0") }
+    }
+}
+
+// Where the text being parsed came from, for "file:line:column: ..." errors.
+// `first_line` is the file line the text starts on (Markdown blocks start mid-file).
+struct Source<'a> {
+    path: &'a Path,
+    first_line: usize,
+}
+
+impl Source<'_> {
+    fn at(&self, line_no: usize, column: usize, msg: impl std::fmt::Display) -> Error {
+        Error::Syntax {
+            path: self.path.to_path_buf(),
+            line: self.first_line + line_no,
+            column,
+            message: msg.to_string(),
+        }
+    }
+}
+
+// the entry an indented line would be nested in
+struct Previous {
+    name: String,
+    line_no: usize,
+    paths: Vec<String>,
+    // where its nodes start in the output, to turn them into directories
+    first_node: usize,
+    is_dir: bool,
+    has_content: bool,
+}
+
+// Structure file format, one entry per line, nesting by indentation:
+//
+//   src/
+//       main.rs <<EOF
+//       fn main() {}
+//       EOF
+//       lib.rs @snippets/lib.rs
+//
+// `<<TAG` takes the following lines up to TAG as the file's content (`<<TAG -n`
+// leaves off the final newline, as `echo -n`), `@path` copies the content of a
+// file next to the structure file.
+// `!include name` and `!exclude path` are handled by include::resolve.
+// A `---` block at the very top holds the header (see spec::Header).
+pub fn parse_structure_file(path: &Path, opts: &ParseOptions) -> Result<Spec> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Cannot read file '{}'", path.display()))?;
+    parse_structure(&content, path, 1, opts)
+}
+
+
+// Same as parse_structure_file for text that came out of `path` starting at
+// line `first_line`, which is where @references are resolved from
+pub fn parse_structure(content: &str, path: &Path, first_line: usize, opts: &ParseOptions) -> Result<Spec> {
+    let source_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let src = Source { path, first_line };
+
+    // indentation of every open level, the first entry sets the outermost one
+    let mut levels: Vec<usize> = Vec::new();
+    // one entry per level below the outermost, holding every path the directory
+    // it's nested in expanded to (`{api,db}/` is two directories)
+    let mut dir_stack: Vec<Vec<String>> = Vec::new();
+    // how far the first nested entry was indented, later ones have to match
+    let mut step = None;
+    let mut tab_style = None;
+    let mut previous: Option<Previous> = None;
+    let mut directives = Vec::new();
+    let mut warnings = Vec::new();
+    // split on '\n' alone so heredoc content keeps any '\r' of CRLF line endings
+    let mut raw_lines = content.split('\n').enumerate().peekable();
+
+    while raw_lines.next_if(|(_, l)| strip_comment(l.trim()).is_empty()).is_some() {}
+    let header = if raw_lines.next_if(|(_, l)| l.trim() == "---").is_some() {
+        parse_header(&mut raw_lines, &src)?
+    } else {
+        Header::default()
+    };
+
+    // Pasted `tree` output (or our own preview) is read by its own parser,
+    // which takes every line and leaves nothing for the indentation loop below
+    let mut lines = if looks_like_tree(raw_lines.clone().map(|(_, l)| l)) {
+        parse_tree_output(&mut raw_lines, &src)?
+    } else {
+        Vec::new()
+    };
+
+    while let Some((line_no, raw_line)) = raw_lines.next() {
+        // blank and comment-only lines don't take part in the nesting
+        let entry = strip_comment(raw_line.trim()).trim_end();
+        if entry.is_empty() {
+            continue;
+        }
+
+        let indent = indent_width(raw_line, line_no, opts, &mut tab_style, &src)?;
+        let column = leading_blanks(raw_line) + 1;
+        let (name, marker) = split_content_marker(entry);
+        let top = levels.last().copied();
+
+        match (top, &mut previous) {
+            (Some(top), Some(parent)) if indent > top => {
+                if !opts.lenient {
+                    let by = indent - top;
+                    match step {
+                        None => step = Some(by),
+                        Some(s) if by == s => {}
+                        Some(s) if by % s == 0 => {
+                            return Err(src.at(line_no, column, format!(
+                                "indented {} levels deeper than the line above, nest one level ({} spaces) at a time",
+                                by / s,
+                                s
+                            )));
+                        }
+                        Some(s) => {
+                            return Err(src.at(line_no, column, format!(
+                                "inconsistent indent of {} spaces, this file indents by {} (expected {})",
+                                indent,
+                                s,
+                                top + s
+                            )));
+                        }
+                    }
+                }
+                if !parent.is_dir {
+                    if !opts.lenient || parent.has_content {
+                        return Err(src.at(line_no, column, format!(
+                            "'{}' is indented under a file ('{}' on line {}), end that name with '/' to make it a directory",
+                            name,
+                            parent.name,
+                            src.first_line + parent.line_no
+                        )));
+                    }
+                    warnings.push(Warning::MadeDirectory {
+                        path: src.path.to_path_buf(),
+                        line: src.first_line + parent.line_no,
+                        name: parent.name.clone(),
+                    });
+                    for node in &mut lines[parent.first_node..] {
+                        node.kind = NodeKind::Dir;
+                    }
+                    parent.is_dir = true;
+                }
+                levels.push(indent);
+                dir_stack.push(parent.paths.clone());
+            }
+            (Some(top), _) if indent < top => {
+                let open = levels.clone();
+                while levels.len() > 1 && levels.last().is_some_and(|l| *l > indent) {
+                    levels.pop();
+                    dir_stack.pop();
+                }
+                // lenient mode keeps an in-between indent at the shallower level
+                if !opts.lenient && levels.last() != Some(&indent) {
+                    let expected: Vec<String> = open.iter().map(|l| l.to_string()).collect();
+                    return Err(src.at(line_no, column, format!(
+                        "inconsistent indent of {} spaces, it doesn't line up with any line above (expected {})",
+                        indent,
+                        expected.join(", ")
+                    )));
+                }
+            }
+            (None, _) => levels.push(indent),
+            _ => {}
+        }
+
+        // `!include` and `!exclude` work in the directory their line is nested in
+        if let Some(directive) = entry.strip_prefix('!') {
+            let (word, arg) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+            if word != "include" && word != "exclude" {
+                return Err(src.at(line_no, column, format!(
+                    "unknown directive '!{}' (quote names that start with '!')",
+                    word
+                )));
+            }
+            let (args, _) = expand_name(arg.trim()).map_err(|e| src.at(line_no, column, e))?;
+            if args.iter().any(String::is_empty) {
+                return Err(src.at(line_no, column, format!("'!{}' needs a template name or path", word)));
+            }
+            let parents = dir_stack.last().cloned().unwrap_or_else(|| vec![String::new()]);
+            for parent in &parents {
+                for arg in &args {
+                    let directive = if word == "include" {
+                        Directive::Include { name: arg.clone(), at: PathBuf::from(parent) }
+                    } else {
+                        Directive::Exclude(Path::new(parent).join(arg))
+                    };
+                    directives.push((lines.len(), directive));
+                }
+            }
+            previous = Some(Previous {
+                name: entry.to_string(),
+                line_no,
+                paths: Vec::new(),
+                first_node: lines.len(),
+                is_dir: false,
+                has_content: true,
+            });
+            continue;
+        }
+
+        let (names, is_dir) = expand_name(name).map_err(|e| src.at(line_no, column, e))?;
+        if names.iter().any(String::is_empty) {
+            return Err(src.at(line_no, column, format!("'{}' has an empty name", name)));
+        }
+        let parents = dir_stack.last().cloned().unwrap_or_else(|| vec![String::new()]);
+        let mut full_paths = Vec::new();
+        for parent in &parents {
+            for n in &names {
+                if parent.is_empty() {
+                    full_paths.push(n.clone());
+                } else {
+                    full_paths.push(format!("{}/{}", parent, n));
+                }
+            }
+        }
+
+        let first_node = lines.len();
+        if is_dir {
+            if marker.is_some() {
+                return Err(src.at(line_no, column, format!("directory '{}' can't have content", name)));
+            }
+            lines.extend(full_paths.iter().map(Node::dir));
+            previous = Some(Previous {
+                name: name.to_string(),
+                line_no,
+                paths: full_paths,
+                first_node,
+                is_dir: true,
+                has_content: false,
+            });
+            continue;
+        }
+
+        let content = match marker {
+            None => None,
+            Some(ContentMarker::Heredoc { tag, newline }) => {
+                let mut block = Vec::new();
+                let indent = loop {
+                    match raw_lines.next() {
+                        Some((_, l)) if l.trim() == tag => break leading_blanks(l),
+                        Some((_, l)) => block.push(l),
+                        None => {
+                            return Err(src.at(line_no, column, format!(
+                                "content of '{}' is missing its closing '{}'",
+                                name,
+                                tag
+                            )));
+                        }
+                    }
+                };
+                let mut text = dedent(&block, indent);
+                if !newline {
+                    text.pop();
+                }
+                Some(text.into_bytes())
+            }
+            Some(ContentMarker::Source(source)) => {
+                let src_path = source_dir.join(source);
+                let bytes = fs::read(&src_path).map_err(|e| {
+                    src.at(line_no, column, format!("cannot read content source '{}': {}", src_path.display(), e))
+                })?;
+                Some(bytes)
+            }
+        };
+        previous = Some(Previous {
+            name: name.to_string(),
+            line_no,
+            paths: full_paths.clone(),
+            first_node,
+            is_dir: false,
+            has_content: content.is_some(),
+        });
+        lines.extend(full_paths.into_iter().map(|p| Node { content: content.clone(), ..Node::file(p) }));
+    }
+
+    if lines.is_empty() && directives.is_empty() && header.extends.is_empty() {
+        bail!("Structure file '{}' is empty.", path.display());
+    }
+
+    Ok(Spec { header, nodes: lines, directives, warnings })
+}
+
+
+// Brace-expands an entry as written, quotes and escapes included, into the
+// names it stands for, and tells whether it is a directory (trailing '/').
+// Keys of the map formats go through here too.
+pub(crate) fn expand_name(text: &str) -> Result<(Vec<String>, bool)> {
+    let escaped = quotes_to_escapes(text)?;
+    let (_, is_dir) = unescape(&escaped)?;
+    let mut names = Vec::new();
+    for expanded in expand_braces(&escaped)? {
+        names.push(unescape(&expanded)?.0);
+    }
+    Ok((names, is_dir))
+}
+
+
+// Columns of indentation in front of a line. Tabs advance to the next tab stop;
+// depending on the policy they're refused, or may not be mixed with spaces
+// anywhere in the file (`style` remembers which one the file started with).
+fn indent_width(
+    line: &str,
+    line_no: usize,
+    opts: &ParseOptions,
+    style: &mut Option<(char, usize)>,
+    src: &Source,
+) -> Result<usize> {
+    let describe = |c: char| if c == '\t' { "tabs" } else { "spaces" };
+    let tab_width = opts.tab_width.get();
+    let mut width: usize = 0;
+    for (i, c) in line.chars().enumerate() {
+        match c {
+            ' ' => width = width.saturating_add(1),
+            '\t' if opts.tabs == TabPolicy::Reject => {
+                return Err(src.at(line_no, i + 1, "tab in indentation, indent with spaces (or use --tabs expand)"));
+            }
+            '\t' => width = width.saturating_add(tab_width - width % tab_width),
+            _ => break,
+        }
+        if opts.tabs != TabPolicy::Strict || opts.lenient {
+            continue;
+        }
+        match *style {
+            None => *style = Some((c, line_no)),
+            Some((s, first)) if s != c => {
+                return Err(src.at(line_no, i + 1, format!(
+                    "indented with {} here but with {} on line {} (use --tabs expand to allow mixing)",
+                    describe(c),
+                    describe(s),
+                    src.first_line + first
+                )));
+            }
+            _ => {}
+        }
+    }
+    Ok(width)
+}
+
+
+// box drawing used by tree(1) and our preview, plus the ASCII variants
+// (`tree --charset ascii` and Windows `tree /A`)
+const TREE_CHARS: &[char] = &['│', '├', '└', '─', '┃', '┣', '┗', '━', '|', '`', '+', '\\', '-', ' ', '\t', '\u{a0}'];
+const TREE_CONNECTORS: &[&str] = &["├", "└", "┣", "┗", "|--", "`--", "+--", "\\--"];
+// icons our preview puts in front of names
+const TREE_ICONS: &[&str] = &["📦", "📁", "📄", "📝", "🦀", "🐍", "🧩", "📘", "🌐", "🎨"];
+
+// Only entry lines count: a file with `<<TAG` content is in our own format,
+// whatever its content looks like, so the first one ends the search
+fn looks_like_tree<'a>(lines: impl Iterator<Item = &'a str>) -> bool {
+    for line in lines {
+        let entry = strip_comment(line.trim()).trim_end();
+        if matches!(split_content_marker(entry).1, Some(ContentMarker::Heredoc { .. })) {
+            return false;
+        }
+        let l = line.trim_start_matches(['│', '┃', '|', ' ', '\t', '\u{a0}']);
+        if TREE_CONNECTORS.iter().any(|c| l.starts_with(c)) {
+            return true;
+        }
+    }
+    false
+}
+
+// splits "│   ├── name" into the column the name starts at and the name
+fn strip_tree_prefix(line: &str) -> (usize, &str) {
+    let rest = line.trim_start_matches(TREE_CHARS);
+    let prefix = &line[..line.len() - rest.len()];
+    // the prefix has to end in whitespace, "├── -x.txt" is a file called "-x.txt"
+    let cut = prefix
+        .char_indices()
+        .filter(|(_, c)| c.is_whitespace())
+        .map(|(i, c)| i + c.len_utf8())
+        .next_back()
+        .unwrap_or(0);
+    (line[..cut].chars().count(), &line[cut..])
+}
+
+// Entries nest under the closest previous entry whose name starts further left.
+// tree(1) doesn't mark directories, so anything with children is one, as is
+// anything with a trailing '/'.
+fn parse_tree_output<'a>(raw_lines: &mut impl Iterator<Item = (usize, &'a str)>, src: &Source) -> Result<Vec<Node>> {
+    let mut nodes: Vec<Node> = Vec::new();
+    // (column, index into nodes) of the entries the next one could be nested in
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+
+    for (line_no, raw_line) in raw_lines {
+        let (column, name) = strip_tree_prefix(strip_comment(raw_line).trim_end());
+        let mut name = name.trim();
+        for icon in TREE_ICONS {
+            if let Some(rest) = name.strip_prefix(icon) {
+                name = rest.trim_start();
+            }
+        }
+        // symlinks are shown as "name -> target"
+        if let Some((link, _)) = name.split_once(" -> ") {
+            name = link.trim_end();
+        }
+
+        if name.is_empty() || (column == 0 && is_tree_noise(name, nodes.is_empty())) {
+            continue;
+        }
+
+        while stack.last().is_some_and(|(col, _)| *col >= column) {
+            stack.pop();
+        }
+        // "." stands for the directory tree was run in, its children are top level
+        if column == 0 && (name == "." || name == "./") {
+            continue;
+        }
+
+        // `tree -Q` quotes every name
+        let (name, is_dir) = quotes_to_escapes(name)
+            .and_then(|n| unescape(&n))
+            .map_err(|e| src.at(line_no, column + 1, e))?;
+
+        let mut path = match stack.last() {
+            Some((_, parent)) => {
+                nodes[*parent].kind = NodeKind::Dir;
+                nodes[*parent].path.clone()
+            }
+            None => PathBuf::new(),
+        };
+        path.push(name);
+
+        let kind = if is_dir { NodeKind::Dir } else { NodeKind::File };
+        nodes.push(Node { path, kind, content: None });
+        stack.push((column, nodes.len() - 1));
+    }
+    Ok(nodes)
+}
+
+// A "dir:" heading before the first entry and the "3 directories, 5 files"
+// summary, both only at the start of a line. Anything else is an entry.
+fn is_tree_noise(line: &str, first: bool) -> bool {
+    if first && line.ends_with(':') {
+        return true;
+    }
+    let counted = |part: &str, words: &[&str]| {
+        part.split_once(' ')
+            .is_some_and(|(n, word)| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) && words.contains(&word))
+    };
+    let mut parts = line.split(", ");
+    let dirs = parts.next().unwrap_or("");
+    let files = parts.next();
+    parts.next().is_none()
+        && counted(dirs, &["directory", "directories"])
+        && files.is_none_or(|f| counted(f, &["file", "files"]))
+}
+
+
+// reads header lines up to and including the closing ---
+fn parse_header<'a>(raw_lines: &mut impl Iterator<Item = (usize, &'a str)>, src: &Source) -> Result<Header> {
+    let mut header = Header::default();
+    for (line_no, line) in raw_lines {
+        let line = line.trim();
+        if line == "---" {
+            return Ok(header);
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(decl) = line.strip_prefix("var ") {
+            let (name, default) = match decl.split_once(':') {
+                Some((name, default)) => (name.trim(), Some(default.trim().to_string())),
+                None => (decl.trim(), None),
+            };
+            if !is_var_name(name) {
+                return Err(src.at(line_no, 1, format!("'{}' is not a valid variable name", name)));
+            }
+            header.vars.push((name.to_string(), default));
+            continue;
+        }
+
+        match line.split_once(':') {
+            Some(("description", value)) => header.description = Some(value.trim().to_string()),
+            Some(("extends", value)) => header
+                .extends
+                .extend(value.split(',').map(str::trim).filter(|v| !v.is_empty()).map(String::from)),
+            Some((key, _)) => return Err(src.at(line_no, 1, format!("unknown header key '{}'", key.trim()))),
+            None => return Err(src.at(line_no, 1, "expected 'key: value' in header")),
+        }
+    }
+    bail!("{}: header is missing its closing '---'", src.path.display())
+}
+
+
+enum ContentMarker<'a> {
+    Heredoc { tag: &'a str, newline: bool },
+    Source(&'a str),
+}
+
+// "main.rs <<EOF" and "main.rs @src/main.rs" both need whitespace before the
+// marker, so names that merely contain '@' or '<<' (or quote them) are left alone
+fn split_content_marker(entry: &str) -> (&str, Option<ContentMarker<'_>>) {
+    if let Some((name, tag)) = entry.rsplit_once(" <<") {
+        let (tag, newline) = match tag.trim().strip_suffix(" -n") {
+            Some(tag) => (tag.trim_end(), false),
+            None => (tag.trim(), true),
+        };
+        if !tag.is_empty() && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !ends_in_quotes(name) {
+            return (name.trim_end(), Some(ContentMarker::Heredoc { tag, newline }));
+        }
+    }
+    if let Some((name, src)) = entry.rsplit_once(" @") {
+        let src = src.trim();
+        if !src.is_empty() && !ends_in_quotes(name) {
+            return (name.trim_end(), Some(ContentMarker::Source(src)));
+        }
+    }
+    (entry, None)
+}
+
+
+fn leading_blanks(line: &str) -> usize {
+    line.bytes().take_while(|b| *b == b' ' || *b == b'\t').count()
+}
+
+
+// the closing tag's indentation is stripped from every line, anything deeper
+// (and the line ending, '\r' included) is kept
+fn dedent(block: &[&str], indent: usize) -> String {
+    let mut out = String::new();
+    for l in block {
+        let strip = leading_blanks(l).min(indent);
+        out.push_str(&l[strip..]);
+        out.push('\n');
+    }
+    out
+}
+
+
+// Positional grammar:
+//   a/b/c.rs   creates the entry relative to the current directory
+//   dir/       creates the directory and moves into it
+//   ..         moves up one directory from where the previous entry was created
+//   :          starts a new group back at the output root
+// Names are quoted and escaped as in structure files (see escape.rs),
+// so '\:' or '":"' is a file called ':'. Comments are left to the shell.
+pub fn parse_groups(tokens: Vec<String>) -> Result<Vec<Vec<Node>>> {
+    let mut groups = Vec::new();
+    let mut current = Vec::new();
+
+    // directory new entries are resolved against
+    let mut cursor = PathBuf::new();
+    // directory of the previous entry, which '..' climbs from
+    let mut last_dir = PathBuf::new();
+
+    for (i, arg) in tokens.iter().enumerate() {
+        let expanded = quotes_to_escapes(arg)
+            .and_then(|a| expand_braces(&a))
+            .with_context(|| format!("invalid argument {} ('{}')", i + 1, arg))?;
+        for token in &expanded {
+            match token.trim() {
+                ":" => {
+                    if !current.is_empty() {
+                        groups.push(std::mem::take(&mut current));
+                    }
+                    cursor.clear();
+                    last_dir.clear();
+                }
+                ".." | "../" => {
+                    if !last_dir.pop() {
+                        bail!("argument {} ('..') would climb above the output root", i + 1);
+                    }
+                    cursor = last_dir.clone();
+                }
+                _ => {
+                    let (name, is_dir) =
+                        unescape(token).with_context(|| format!("invalid argument {} ('{}')", i + 1, arg))?;
+                    let node = if is_dir { Node::dir(name) } else { Node::file(name) };
+                    let path = resolve_relative(&cursor, &node.path)
+                        .with_context(|| format!("invalid argument {} ('{}')", i + 1, arg))?;
+                    if path.as_os_str().is_empty() {
+                        continue;
+                    }
+
+                    match node.kind {
+                        NodeKind::Dir => {
+                            cursor = path.clone();
+                            last_dir = path.clone();
+                        }
+                        NodeKind::File => {
+                            last_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+                        }
+                    }
+                    current.push(Node { path, ..node });
+                }
+            }
+        }
+    }
+    if !current.is_empty() {
+        groups.push(current);
+    }
+    Ok(groups)
+}
+
+
+fn resolve_relative(cursor: &Path, rel: &Path) -> Result<PathBuf> {
+    let mut out = cursor.to_path_buf();
+    for comp in rel.components() {
+        match comp {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    bail!("'..' would climb above the output root");
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                bail!("absolute paths are not allowed");
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Spec> {
+        parse_structure(text, Path::new("test.txt"), 1, &ParseOptions::default())
+    }
+
+    fn paths(spec: &Spec) -> Vec<String> {
+        spec.nodes.iter().map(|n| n.path.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn lenient_parsing_reports_files_made_directories() {
+        let opts = ParseOptions { lenient: true, ..Default::default() };
+        let spec = parse_structure("a.txt\n    b.txt\n", Path::new("test.txt"), 1, &opts).unwrap();
+        assert_eq!(spec.nodes[0].kind, NodeKind::Dir);
+        assert_eq!(
+            spec.warnings,
+            [Warning::MadeDirectory { path: PathBuf::from("test.txt"), line: 1, name: "a.txt".to_string() }]
+        );
+    }
+
+    #[test]
+    fn heredoc_content_doesnt_make_a_tree_paste() {
+        let spec = parse("README.md <<EOF\n├── src\n`--verbose` prints more\nEOF\nsrc/\n").unwrap();
+        assert_eq!(paths(&spec), ["README.md", "src"]);
+        assert_eq!(spec.nodes[0].content.as_deref(), Some("├── src\n`--verbose` prints more\n".as_bytes()));
+    }
+
+    fn syntax_error(text: &str, opts: &ParseOptions) -> (usize, usize, String) {
+        match parse_structure(text, Path::new("test.txt"), 1, opts) {
+            Err(Error::Syntax { line, column, message, .. }) => (line, column, message),
+            other => panic!("expected a syntax error, got {:?}", other.map(|s| paths(&s))),
+        }
+    }
+
+    #[test]
+    fn heredocs_and_content_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("lib.rs"), "pub fn lib() {}\n").unwrap();
+        let text = "src/\n    main.rs <<EOF\n    fn main() {\n        run();\n    }\n    EOF\n    lib.rs @lib.rs\n    VERSION <<END -n\n    1.0\n    END\n";
+        let spec = parse_structure(text, &dir.path().join("structure.txt"), 1, &ParseOptions::default()).unwrap();
+        assert_eq!(paths(&spec), ["src", "src/main.rs", "src/lib.rs", "src/VERSION"]);
+        assert_eq!(spec.nodes[1].content.as_deref(), Some("fn main() {\n    run();\n}\n".as_bytes()));
+        assert_eq!(spec.nodes[2].content.as_deref(), Some("pub fn lib() {}\n".as_bytes()));
+        assert_eq!(spec.nodes[3].content.as_deref(), Some("1.0".as_bytes()));
+
+        let (line, _, message) = syntax_error("a.txt <<EOF\ntext\n", &ParseOptions::default());
+        assert_eq!(line, 1);
+        assert!(message.contains("missing its closing 'EOF'"), "{}", message);
+        let (_, _, message) = syntax_error("a.txt @missing.txt\n", &ParseOptions::default());
+        assert!(message.contains("cannot read content source"), "{}", message);
+    }
+
+    #[test]
+    fn reads_tree_output() {
+        let text = "\
+.
+├── Cargo.toml
+├── src
+│   ├── main.rs
+│   └── util
+│       └── mod.rs
+└── tests/
+
+3 directories, 3 files
+";
+        let spec = parse(text).unwrap();
+        assert_eq!(paths(&spec), ["Cargo.toml", "src", "src/main.rs", "src/util", "src/util/mod.rs", "tests"]);
+        let dirs: Vec<&PathBuf> = spec.nodes.iter().filter(|n| n.kind == NodeKind::Dir).map(|n| &n.path).collect();
+        assert_eq!(dirs, ["src", "src/util", "tests"]);
+    }
+
+    #[test]
+    fn only_headings_and_the_summary_are_dropped() {
+        let text = "src:\n├── 2 files.txt\n├── (draft)\n├── notes:\n└── 1 directory\n\n1 directory, 4 files\n2 directories\n";
+        let spec = parse(text).unwrap();
+        assert_eq!(paths(&spec), ["2 files.txt", "(draft)", "notes:", "1 directory"]);
+    }
+
+    #[test]
+    fn reads_ascii_tree_output() {
+        let text = "project\n|-- \"a b.txt\"\n|-- lib\n|   `-- link -> ../x\n`-- -x.txt\n";
+        let spec = parse(text).unwrap();
+        assert_eq!(paths(&spec), ["project", "project/a b.txt", "project/lib", "project/lib/link", "project/-x.txt"]);
+        assert_eq!(spec.nodes[0].kind, NodeKind::Dir);
+    }
+
+    #[test]
+    fn indentation_nests_entries() {
+        let spec = parse("# layout\nsrc/\n    bin/\n        main.rs\n    lib.rs\n\nREADME.md\n").unwrap();
+        assert_eq!(paths(&spec), ["src", "src/bin", "src/bin/main.rs", "src/lib.rs", "README.md"]);
+        let kinds: Vec<NodeKind> = spec.nodes.iter().map(|n| n.kind).collect();
+        assert_eq!(kinds, [NodeKind::Dir, NodeKind::Dir, NodeKind::File, NodeKind::File, NodeKind::File]);
+    }
+
+    #[test]
+    fn bad_indentation_points_at_the_line() {
+        let opts = ParseOptions::default();
+        let (line, column, message) = syntax_error("src/\n    a.rs\n      b.rs\n", &opts);
+        assert_eq!((line, column), (3, 7));
+        assert!(message.contains("inconsistent indent"), "{}", message);
+
+        let (line, column, message) = syntax_error("a.rs\n    b.rs\n", &opts);
+        assert_eq!((line, column), (2, 5));
+        assert!(message.contains("indented under a file"), "{}", message);
+
+        let (line, _, message) = syntax_error("src/\n    a/\n            b.rs\n", &opts);
+        assert_eq!(line, 3);
+        assert!(message.contains("2 levels deeper"), "{}", message);
+    }
+
+    #[test]
+    fn tab_policies() {
+        let text = "src/\n\tlib.rs\n\tbin/\n\t    main.rs\n";
+        let (line, _, message) = syntax_error(text, &ParseOptions::default());
+        assert_eq!(line, 4);
+        assert!(message.contains("tabs on line 2"), "{}", message);
+
+        let expand = ParseOptions { tabs: TabPolicy::Expand, ..Default::default() };
+        let spec = parse_structure(text, Path::new("test.txt"), 1, &expand).unwrap();
+        assert_eq!(paths(&spec), ["src", "src/lib.rs", "src/bin", "src/bin/main.rs"]);
+
+        let reject = ParseOptions { tabs: TabPolicy::Reject, ..Default::default() };
+        let (line, column, _) = syntax_error(text, &reject);
+        assert_eq!((line, column), (2, 1));
+
+        let wide = ParseOptions { tabs: TabPolicy::Expand, tab_width: NonZeroUsize::MAX, ..Default::default() };
+        let spec = parse_structure("src/\n\t\t lib.rs\n", Path::new("test.txt"), 1, &wide).unwrap();
+        assert_eq!(paths(&spec), ["src", "src/lib.rs"]);
+    }
+
+    #[test]
+    fn quoted_and_escaped_names() {
+        let spec = parse("\"my docs\"/\n    \"{a,b}.txt\"\n    \\#draft.md\n    \"!notes\"\nC#/\n").unwrap();
+        assert_eq!(paths(&spec), ["my docs", "my docs/{a,b}.txt", "my docs/#draft.md", "my docs/!notes", "C#"]);
+        assert!(matches!(parse("!frobnicate x\n"), Err(Error::Syntax { .. })));
+    }
+
+    fn groups(args: &[&str]) -> Result<Vec<Vec<String>>> {
+        let groups = parse_groups(args.iter().map(|a| a.to_string()).collect())?;
+        Ok(groups
+            .iter()
+            .map(|g| {
+                g.iter()
+                    .map(|n| {
+                        let slash = if n.kind == NodeKind::Dir { "/" } else { "" };
+                        format!("{}{}", n.path.display(), slash)
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    #[test]
+    fn arguments_nest_under_directories() {
+        assert_eq!(
+            groups(&["src/", "bin/", "main.rs", "..", "lib.rs", "..", "README.md"]).unwrap(),
+            [["src/", "src/bin/", "src/bin/main.rs", "src/lib.rs", "README.md"]]
+        );
+        // '..' climbs from where the previous entry was made, not from the cursor
+        assert_eq!(groups(&["a/", "b/c.txt", "..", "d.txt"]).unwrap(), [["a/", "a/b/c.txt", "a/d.txt"]]);
+        assert_eq!(groups(&["a/", "../b.txt", "c/d/", "e.txt"]).unwrap(), [["a/", "b.txt", "a/c/d/", "a/c/d/e.txt"]]);
+        assert_eq!(groups(&["src/", "{a,b}.rs", "..", "c.rs"]).unwrap(), [["src/", "src/a.rs", "src/b.rs", "c.rs"]]);
+    }
+
+    #[test]
+    fn climbing_above_the_root_is_an_error() {
+        assert!(groups(&[".."]).is_err());
+        assert!(groups(&["a.txt", ".."]).is_err());
+        assert!(groups(&["a/", "..", ".."]).is_err());
+        assert!(groups(&["a/", "../../b.txt"]).is_err());
+        assert!(groups(&["/etc/passwd"]).is_err());
+    }
+
+    #[test]
+    fn colons_start_new_groups() {
+        assert_eq!(
+            groups(&["a/", "x.txt", ":", "y.txt", ":", ":", "b/", ":"]).unwrap(),
+            [vec!["a/", "a/x.txt"], vec!["y.txt"], vec!["b/"]]
+        );
+        assert_eq!(groups(&[":", "a.txt"]).unwrap(), [["a.txt"]]);
+        assert!(groups(&[":", ":"]).unwrap().is_empty());
+        // an escaped or quoted colon is a name
+        assert_eq!(groups(&["\\:", "\":\""]).unwrap(), [[":", ":"]]);
+    }
+}
@@ -0,0 +1,225 @@
+use colored::*;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::conflict::ConflictPolicy;
+use crate::error::{Result, bail};
+use crate::filesystem::{EntryKind, FileSystem};
+use crate::node::{Node, NodeKind};
+
+// What running treegen would do to one path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Create,
+    // already there and kept as is
+    Exists,
+    // already there and left alone; missing entries inside a skipped
+    // directory are still created
+    Skip,
+    Overwrite,
+    // renamed to <name>.bak, then created
+    Backup,
+    // needs a decision (prompt) or will fail, with the reason
+    Conflict(String),
+}
+
+pub struct Step {
+    pub path: PathBuf,
+    pub kind: NodeKind,
+    pub action: Action,
+}
+
+pub struct Plan {
+    pub steps: Vec<Step>,
+}
+
+impl Plan {
+    // `policy` is the effective conflict policy, None when the user will be asked
+    pub fn build(fs: &dyn FileSystem, all_paths: &BTreeMap<PathBuf, Node>, policy: Option<ConflictPolicy>, force_replace_dirs: bool) -> Plan {
+        let steps = all_paths
+            .iter()
+            .map(|(path, node)| Step {
+                path: path.clone(),
+                kind: node.kind,
+                action: classify(fs, node, policy, force_replace_dirs),
+            })
+            .collect();
+        Plan { steps }
+    }
+
+    pub fn count(&self, f: impl Fn(&Action) -> bool) -> usize {
+        self.steps.iter().filter(|s| f(&s.action)).count()
+    }
+
+    // terraform style listing, one line per path, followed by the totals
+    pub fn print(&self, base: &Path) {
+        println!("{}", "Planned changes:".bold());
+        for step in &self.steps {
+            let rel = step.path.strip_prefix(base).unwrap_or(&step.path);
+            let mut name = rel.display().to_string();
+            if step.kind == NodeKind::Dir {
+                name.push('/');
+            }
+            match &step.action {
+                Action::Create => println!("  {} {}", "+".green(), name.green()),
+                Action::Exists => println!("  {} {} {}", "=".dimmed(), name.dimmed(), "(exists)".dimmed()),
+                Action::Skip => println!("  {} {} {}", "-".dimmed(), name.dimmed(), "(skipped)".dimmed()),
+                Action::Overwrite => println!("  {} {} {}", "~".yellow(), name.yellow(), "(overwrite)".yellow()),
+                Action::Backup => println!("  {} {} {}", "~".yellow(), name.yellow(), "(backup, then create)".yellow()),
+                Action::Conflict(reason) => println!("  {} {} {}", "!".red(), name.red(), format!("({})", reason).red()),
+            }
+        }
+
+        println!(
+            "\n{} {} to create, {} to overwrite, {} to back up, {} unchanged, {} skipped, {} conflict(s).",
+            "Plan:".bold(),
+            self.count(|a| *a == Action::Create).to_string().green(),
+            self.count(|a| *a == Action::Overwrite).to_string().yellow(),
+            self.count(|a| *a == Action::Backup).to_string().yellow(),
+            self.count(|a| *a == Action::Exists),
+            self.count(|a| *a == Action::Skip),
+            self.count(|a| matches!(a, Action::Conflict(_))).to_string().red(),
+        );
+    }
+}
+
+// Mirrors the decisions create_path makes, without touching anything
+fn classify(fs: &dyn FileSystem, node: &Node, policy: Option<ConflictPolicy>, force_replace_dirs: bool) -> Action {
+    let path = node.path.as_path();
+    let Some(kind) = fs.kind(path) else {
+        return Action::Create;
+    };
+
+    let is_dir = kind == EntryKind::Dir;
+    if is_dir && node.kind == NodeKind::Dir && !force_replace_dirs {
+        return Action::Exists;
+    }
+    let found = if is_dir { NodeKind::Dir } else { NodeKind::File };
+
+    match policy {
+        None => Action::Conflict("exists, will ask".into()),
+        Some(ConflictPolicy::Skip) => Action::Skip,
+        Some(ConflictPolicy::Merge) if found == node.kind => Action::Exists,
+        Some(ConflictPolicy::Merge) => Action::Conflict(format!(
+            "exists as a {}, declared as a {}",
+            found.as_str(),
+            node.kind.as_str()
+        )),
+        Some(ConflictPolicy::Error) => Action::Conflict("already exists".into()),
+        Some(ConflictPolicy::Overwrite) => {
+            let non_empty = is_dir && fs.read_dir(path).is_ok_and(|entries| !entries.is_empty());
+            if non_empty && !force_replace_dirs {
+                Action::Conflict("non-empty directory, needs --force-replace-dirs".into())
+            } else {
+                Action::Overwrite
+            }
+        }
+        Some(ConflictPolicy::Backup) => Action::Backup,
+    }
+}
+
+
+// Every entry of the groups under `base`, keyed by full path, with the
+// directories leading up to each one added as entries of their own
+pub fn collect_groups(base: &Path, groups: &[Vec<Node>], all_paths: &mut BTreeMap<PathBuf, Node>) -> Result<()> {
+    for group in groups {
+        for node in group {
+            if node.path.as_os_str().is_empty() {
+                continue;
+            }
+
+            // entries must stay inside the output directory, also after variable substitution
+            if node.path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+                bail!("'{}' points outside the output directory", node.path.display());
+            }
+
+            // Construct full path relative to output directory
+            let path = base.join(&node.path);
+
+            // every ancestor below the base is a directory
+            let mut parent = path.parent();
+            while let Some(dir) = parent {
+                if dir == base || dir.as_os_str().is_empty() {
+                    break;
+                }
+                insert_node(all_paths, Node::dir(dir))?;
+                parent = dir.parent();
+            }
+
+            insert_node(all_paths, Node { path, ..node.clone() })?;
+        }
+    }
+
+    Ok(())
+}
+
+
+fn insert_node(all_paths: &mut BTreeMap<PathBuf, Node>, node: Node) -> Result<()> {
+    if let Some(existing) = all_paths.get(&node.path)
+        && existing.kind != node.kind
+    {
+        bail!("'{}' is declared both as a file and as a directory", node.path.display());
+    }
+    all_paths.insert(node.path.clone(), node);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFs;
+
+    // an existing file `file.txt`, an empty directory `empty` and `full` with a file in it
+    fn existing() -> MemoryFs {
+        let fs = MemoryFs::new();
+        fs.write(Path::new("file.txt"), b"").unwrap();
+        fs.create_dir(Path::new("empty")).unwrap();
+        fs.create_dir(Path::new("full")).unwrap();
+        fs.write(Path::new("full/a.txt"), b"").unwrap();
+        fs
+    }
+
+    fn action(node: Node, policy: Option<ConflictPolicy>, force_replace_dirs: bool) -> Action {
+        let all_paths = BTreeMap::from([(node.path.clone(), node)]);
+        let mut plan = Plan::build(&existing(), &all_paths, policy, force_replace_dirs);
+        plan.steps.remove(0).action
+    }
+
+    fn conflict(reason: &str) -> Action {
+        Action::Conflict(reason.to_string())
+    }
+
+    #[test]
+    fn missing_entries_are_created_and_existing_directories_kept() {
+        for policy in [None, Some(ConflictPolicy::Skip), Some(ConflictPolicy::Overwrite), Some(ConflictPolicy::Error)] {
+            assert_eq!(action(Node::file("new.txt"), policy, false), Action::Create);
+            assert_eq!(action(Node::dir("full"), policy, false), Action::Exists);
+        }
+    }
+
+    #[test]
+    fn existing_entries_follow_the_policy() {
+        let file = || Node::file("file.txt");
+        assert_eq!(action(file(), None, false), conflict("exists, will ask"));
+        assert_eq!(action(file(), Some(ConflictPolicy::Skip), false), Action::Skip);
+        assert_eq!(action(file(), Some(ConflictPolicy::Merge), false), Action::Exists);
+        assert_eq!(action(file(), Some(ConflictPolicy::Error), false), conflict("already exists"));
+        assert_eq!(action(file(), Some(ConflictPolicy::Overwrite), false), Action::Overwrite);
+        assert_eq!(action(file(), Some(ConflictPolicy::Backup), false), Action::Backup);
+    }
+
+    #[test]
+    fn file_and_directory_mismatches() {
+        let merge = Some(ConflictPolicy::Merge);
+        assert_eq!(action(Node::file("full"), merge, false), conflict("exists as a directory, declared as a file"));
+        assert_eq!(action(Node::dir("file.txt"), merge, false), conflict("exists as a file, declared as a directory"));
+
+        let overwrite = Some(ConflictPolicy::Overwrite);
+        assert_eq!(action(Node::file("full"), overwrite, false), conflict("non-empty directory, needs --force-replace-dirs"));
+        assert_eq!(action(Node::file("full"), overwrite, true), Action::Overwrite);
+        assert_eq!(action(Node::file("empty"), overwrite, false), Action::Overwrite);
+        assert_eq!(action(Node::dir("file.txt"), overwrite, false), Action::Overwrite);
+        // with --force-replace-dirs existing directories are conflicts like files
+        assert_eq!(action(Node::dir("full"), Some(ConflictPolicy::Skip), true), Action::Skip);
+    }
+}
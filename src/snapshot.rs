@@ -0,0 +1,164 @@
+use crate::error::{Context, Result, bail};
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+use std::fs;
+use std::path::Path;
+
+use crate::escape::quote_name;
+use crate::warning::Warning;
+
+pub struct SnapshotOptions<'a> {
+    pub with_content: bool,
+    pub max_depth: Option<usize>,
+    pub exclude: &'a [String],
+    pub description: Option<&'a str>,
+}
+
+pub struct Snapshot {
+    pub text: String,
+    pub warnings: Vec<Warning>,
+}
+
+// Walks `dir` (honoring .gitignore) and writes it out in the indented
+// format parse_structure_file reads
+pub fn snapshot(dir: &Path, opts: &SnapshotOptions) -> Result<Snapshot> {
+    if !dir.is_dir() {
+        bail!("'{}' is not a directory", dir.display());
+    }
+
+    let mut overrides = OverrideBuilder::new(dir);
+    overrides.add("!.git/")?;
+    for glob in opts.exclude {
+        overrides
+            .add(&format!("!{}", glob))
+            .with_context(|| format!("Invalid exclude pattern '{}'", glob))?;
+    }
+
+    let walker = WalkBuilder::new(dir)
+        .hidden(false)
+        .require_git(false)
+        .max_depth(opts.max_depth)
+        .overrides(overrides.build()?)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    let mut out = String::new();
+    let mut warnings = Vec::new();
+    if let Some(description) = opts.description {
+        out.push_str(&format!("---\ndescription: {}\n---\n", description));
+    }
+
+    for entry in walker {
+        let entry = entry?;
+        // depth 0 is the directory itself
+        if entry.depth() == 0 {
+            continue;
+        }
+        let indent = "    ".repeat(entry.depth() - 1);
+        let name = quote_name(&entry.file_name().to_string_lossy());
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+
+        if is_dir {
+            out.push_str(&format!("{}{}/\n", indent, name));
+            continue;
+        }
+
+        let content = if opts.with_content {
+            let text = read_text(entry.path())?;
+            if text.is_none() {
+                warnings.push(Warning::NotText(entry.path().to_path_buf()));
+            }
+            text
+        } else {
+            None
+        };
+        match content {
+            Some(text) if !text.is_empty() => {
+                // contents are read back with {{placeholders}} filled in (see vars.rs)
+                let text = text.replace("{{", "\\{{");
+                let tag = heredoc_tag(&text);
+                // a file without a final newline is read back without one
+                let flag = if text.ends_with('\n') { "" } else { " -n" };
+                out.push_str(&format!("{}{} <<{}{}\n", indent, name, tag, flag));
+                // split on '\n' alone, CRLF line endings are kept as they are
+                for line in text.strip_suffix('\n').unwrap_or(&text).split('\n') {
+                    if line.is_empty() {
+                        out.push('\n');
+                    } else {
+                        out.push_str(&format!("{}    {}\n", indent, line));
+                    }
+                }
+                out.push_str(&format!("{}    {}\n", indent, tag));
+            }
+            _ => out.push_str(&format!("{}{}\n", indent, name)),
+        }
+    }
+    Ok(Snapshot { text: out, warnings })
+}
+
+// None for binary files, which are saved empty
+fn read_text(path: &Path) -> Result<Option<String>> {
+    let bytes = fs::read(path).with_context(|| format!("Cannot read '{}'", path.display()))?;
+    Ok(String::from_utf8(bytes).ok())
+}
+
+// a terminator that doesn't show up as a line of the content
+fn heredoc_tag(text: &str) -> String {
+    let mut tag = "EOF".to_string();
+    let mut n = 1;
+    while text.lines().any(|l| l.trim() == tag) {
+        tag = format!("EOF{}", n);
+        n += 1;
+    }
+    tag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{ParseOptions, parse_structure};
+    use crate::vars::{self, Vars};
+
+    #[test]
+    fn saved_structure_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let readme = "# Layout\n\n```\n.\n├── src\n│   └── main.rs\n`-- Cargo.toml\n```\n\n    indented\nEOF\n";
+        let page = "<h1>{{ title }}</h1> \\{{ raw }} {{{ body }}}\n";
+        fs::create_dir_all(root.join("src/bin")).unwrap();
+        fs::write(root.join("README.md"), readme).unwrap();
+        fs::write(root.join("page.vue"), page).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("src/bin/tool.rs"), "\n\nfn main() {}\n\n").unwrap();
+        fs::write(root.join("empty file # 1"), "").unwrap();
+        fs::write(root.join("windows.bat"), "@echo off\r\n\r\necho hi\r\n").unwrap();
+
+        let opts = SnapshotOptions { with_content: true, max_depth: None, exclude: &[], description: None };
+        let saved = snapshot(root, &opts).unwrap();
+        assert!(saved.warnings.is_empty());
+        let spec = parse_structure(&saved.text, Path::new("saved.txt"), 1, &ParseOptions::default()).unwrap();
+        let mut groups = vec![spec.nodes];
+        let values = vars::resolve(&spec.header, &groups, Vars::new(), &[], false).unwrap();
+        vars::apply(&mut groups, &values);
+
+        let read: Vec<_> = groups[0]
+            .iter()
+            .map(|n| (n.path.to_string_lossy().into_owned(), n.content.as_deref().map(String::from_utf8_lossy)))
+            .collect();
+        let expected = [
+            ("README.md", Some(readme)),
+            ("empty file # 1", None),
+            ("page.vue", Some(page)),
+            ("src", None),
+            ("src/bin", None),
+            ("src/bin/tool.rs", Some("\n\nfn main() {}\n\n")),
+            ("src/main.rs", Some("fn main() {}")),
+            ("windows.bat", Some("@echo off\r\n\r\necho hi\r\n")),
+        ];
+        assert_eq!(read.len(), expected.len());
+        for ((path, content), (want_path, want_content)) in read.iter().zip(expected) {
+            assert_eq!(path, want_path);
+            assert_eq!(content.as_deref(), want_content);
+        }
+    }
+}
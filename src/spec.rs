@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use crate::node::Node;
+use crate::warning::Warning;
+
+// Optional front matter of a structure file:
+//
+//   ---
+//   description: Rust command line app
+//   var project_name: my-app
+//   var author
+//   extends: base, ci
+//   ---
+#[derive(Debug, Default, Clone)]
+pub struct Header {
+    pub description: Option<String>,
+    // templates whose entries come before this file's own
+    pub extends: Vec<String>,
+    // declared variables, with their default if they have one
+    pub vars: Vec<(String, Option<String>)>,
+}
+
+// `!include` and `!exclude` lines, see include::resolve
+#[derive(Debug, Clone)]
+pub enum Directive {
+    // the entries of another template, placed under `at`
+    Include { name: String, at: PathBuf },
+    // drops the path and everything under it from the entries before it
+    Exclude(PathBuf),
+}
+
+// A parsed structure: the header plus the entries in file order
+#[derive(Debug, Default, Clone)]
+pub struct Spec {
+    pub header: Header,
+    pub nodes: Vec<Node>,
+    // each directive with the number of entries that came before it
+    pub directives: Vec<(usize, Directive)>,
+    // from reading this file and everything it extends or includes
+    pub warnings: Vec<Warning>,
+}
@@ -0,0 +1,314 @@
+use crate::error::{Context, Error, Result, bail};
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::formats::{self, parse_spec_file};
+use crate::include;
+use crate::parse::ParseOptions;
+use crate::spec::{Directive, Spec};
+
+// where a directory of the search path comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Flag,
+    Project,
+    SearchPath,
+    User,
+    System,
+}
+
+impl Origin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Origin::Flag => "--template-dir",
+            Origin::Project => "project",
+            Origin::SearchPath => "TREEGEN_TEMPLATE_PATH",
+            Origin::User => "user",
+            Origin::System => "XDG_DATA_DIRS",
+        }
+    }
+}
+
+pub struct Template {
+    pub name: String,
+    pub path: PathBuf,
+    // a directory earlier in the search path has one of the same name
+    pub shadowed: bool,
+}
+
+// Template directories in lookup order, earlier ones shadow later ones:
+//
+//   --template-dir DIR                   (repeatable)
+//   .treegen/templates/                  in the current directory or the closest parent with one
+//   $TREEGEN_TEMPLATE_PATH               colon-separated
+//   $XDG_CONFIG_HOME/treegen/templates   (~/.config/treegen/templates)
+//   $XDG_DATA_DIRS/treegen/templates     (/usr/local/share, /usr/share)
+//
+// New templates are written to the first --template-dir, or else the user directory.
+pub struct Store {
+    dirs: Vec<(PathBuf, Origin)>,
+}
+
+impl Store {
+    pub fn new(flag_dirs: &[PathBuf]) -> Self {
+        let search_path = env::var_os("TREEGEN_TEMPLATE_PATH");
+        Store::ordered(flag_dirs, project_dir(), search_path.as_deref(), user_dir(), system_dirs())
+    }
+
+    // the directories in lookup order, whatever the environment says they are
+    fn ordered(
+        flag_dirs: &[PathBuf],
+        project: Option<PathBuf>,
+        search_path: Option<&OsStr>,
+        user: Option<PathBuf>,
+        system: Vec<PathBuf>,
+    ) -> Self {
+        let mut dirs: Vec<(PathBuf, Origin)> = flag_dirs.iter().map(|d| (d.clone(), Origin::Flag)).collect();
+        if let Some(project) = project {
+            dirs.push((project, Origin::Project));
+        }
+        if let Some(path) = search_path {
+            dirs.extend(
+                env::split_paths(path)
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .map(|p| (p, Origin::SearchPath)),
+            );
+        }
+        if let Some(user) = user {
+            dirs.push((user, Origin::User));
+        }
+        dirs.extend(system.into_iter().map(|d| (d, Origin::System)));
+
+        // a directory listed twice counts where it comes first
+        let mut seen = HashSet::new();
+        dirs.retain(|(dir, _)| seen.insert(fs::canonicalize(dir).unwrap_or_else(|_| dir.clone())));
+        Store { dirs }
+    }
+
+    pub fn dirs(&self) -> &[(PathBuf, Origin)] {
+        &self.dirs
+    }
+
+    // where new templates are written
+    pub fn write_dir(&self) -> Result<PathBuf> {
+        self.dirs
+            .iter()
+            .find(|(_, origin)| matches!(origin, Origin::Flag | Origin::User))
+            .map(|(dir, _)| dir.clone())
+            .ok_or_else(|| Error::Invalid("No directory to save templates in: there is no home directory, set XDG_CONFIG_HOME or pass --template-dir".to_string()))
+    }
+
+    // "dir, dir, dir" for not-found messages
+    pub fn searched(&self) -> String {
+        let dirs: Vec<String> = self.dirs.iter().map(|(dir, _)| dir.display().to_string()).collect();
+        dirs.join(", ")
+    }
+
+    // The first <name>.txt, or <name>.yaml/.yml/.json/.toml, along the search path
+    pub fn get_template_path(&self, name: &str) -> Option<PathBuf> {
+        self.dirs.iter().find_map(|(dir, _)| file_in(dir, name))
+    }
+
+    // Every template in every directory, in lookup order, shadowed ones included
+    pub fn all(&self) -> Result<Vec<Template>> {
+        let mut found = Vec::new();
+        let mut seen = HashSet::new();
+        for (dir, _) in &self.dirs {
+            for name in names_in(dir)? {
+                let Some(path) = file_in(dir, &name) else {
+                    continue;
+                };
+                let shadowed = !seen.insert(name.clone());
+                found.push(Template { name, path, shadowed });
+            }
+        }
+        Ok(found)
+    }
+
+    // the templates a name resolves to, by name
+    pub fn installed(&self) -> Result<Vec<Template>> {
+        let mut installed: Vec<Template> = self.all()?.into_iter().filter(|t| !t.shadowed).collect();
+        installed.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(installed)
+    }
+
+    // A structure file with its `extends:` and `!include`s resolved
+    pub fn load(&self, path: &Path, block: Option<&str>, opts: &ParseOptions) -> Result<Spec> {
+        let spec = parse_spec_file(path, block, opts)?;
+        include::resolve(spec, path, &|name| self.get_template_path(name), opts)
+    }
+
+    // installed templates that extend or include `name`
+    pub fn dependents(&self, name: &str) -> Result<Vec<String>> {
+        let mut found = Vec::new();
+        for template in self.installed()? {
+            let Ok(spec) = parse_spec_file(&template.path, None, &ParseOptions::default()) else {
+                continue;
+            };
+            if template.name != name && references(&spec).contains(&name) {
+                found.push(template.name);
+            }
+        }
+        Ok(found)
+    }
+}
+
+
+// names of the templates a structure extends or includes directly
+pub fn references(spec: &Spec) -> Vec<&str> {
+    let included = spec.directives.iter().filter_map(|(_, d)| match d {
+        Directive::Include { name, .. } => Some(name.as_str()),
+        Directive::Exclude(_) => None,
+    });
+    spec.header.extends.iter().map(String::as_str).chain(included).collect()
+}
+
+
+// A template name becomes a file name in a template directory, so it can't be
+// empty or point anywhere else
+pub fn check_name(name: &str) -> Result<()> {
+    if name.trim().is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        bail!("'{}' is not a valid template name, it can't be empty or contain '/', '\\' or '..'", name);
+    }
+    Ok(())
+}
+
+
+// <name>.txt, or the first of <name>.yaml/.yml/.json/.toml that exists in `dir`
+pub fn file_in(dir: &Path, name: &str) -> Option<PathBuf> {
+    check_name(name).ok()?;
+    std::iter::once("txt")
+        .chain(formats::MAP_EXTENSIONS.iter().copied())
+        .map(|ext| dir.join(format!("{}.{}", name, ext)))
+        .find(|p| p.is_file())
+}
+
+
+// template names in one directory, sorted
+fn names_in(dir: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Cannot read '{}'", dir.display())),
+    };
+
+    let mut names = Vec::new();
+    for entry in entries {
+        let path = entry.with_context(|| format!("Cannot read '{}'", dir.display()))?.path();
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if !path.is_file() || (ext != "txt" && !formats::MAP_EXTENSIONS.contains(&ext)) {
+            continue;
+        }
+        if let Some(stem) = path.file_stem() {
+            names.push(stem.to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+
+// .treegen/templates in the current directory or the closest parent that has one
+fn project_dir() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(".treegen").join("templates"))
+        .find(|dir| dir.is_dir())
+}
+
+
+// XDG base directory variables only count when set to an absolute path
+fn xdg_var(name: &str) -> Option<PathBuf> {
+    env::var_os(name).map(PathBuf::from).filter(|p| p.is_absolute())
+}
+
+
+fn user_dir() -> Option<PathBuf> {
+    let config = xdg_var("XDG_CONFIG_HOME").or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config.join("treegen").join("templates"))
+}
+
+
+fn system_dirs() -> Vec<PathBuf> {
+    let data_dirs = env::var_os("XDG_DATA_DIRS")
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    env::split_paths(&data_dirs)
+        .filter(|p| p.is_absolute())
+        .map(|p| p.join("treegen").join("templates"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_stay_inside_the_template_directory() {
+        for name in ["rust", "my-template", "web.v2"] {
+            assert!(check_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", " ", "../escaped", "..", "a/b", "a\\b", "/etc/passwd"] {
+            assert!(check_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn lookups_ignore_invalid_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let templates = dir.path().join("templates");
+        fs::create_dir(&templates).unwrap();
+        fs::write(dir.path().join("outside.txt"), "a.txt\n").unwrap();
+        fs::write(templates.join("inside.txt"), "a.txt\n").unwrap();
+
+        assert_eq!(file_in(&templates, "inside"), Some(templates.join("inside.txt")));
+        assert_eq!(file_in(&templates, "../outside"), None);
+    }
+
+    #[test]
+    fn earlier_directories_shadow_later_ones() {
+        let root = tempfile::tempdir().unwrap();
+        let levels = ["flag", "project", "path-a", "path-b", "user", "system"];
+        for level in levels {
+            fs::create_dir(root.path().join(level)).unwrap();
+        }
+        let dir = |level: &str| root.path().join(level);
+        let search_path = env::join_paths([dir("path-a"), dir("path-b")]).unwrap();
+        let store = || Store::ordered(&[dir("flag")], Some(dir("project")), Some(&search_path), Some(dir("user")), vec![dir("system")]);
+
+        let origins: Vec<Origin> = store().dirs().iter().map(|(_, origin)| *origin).collect();
+        assert_eq!(
+            origins,
+            [Origin::Flag, Origin::Project, Origin::SearchPath, Origin::SearchPath, Origin::User, Origin::System]
+        );
+
+        // `shared` is in every directory from `level` on, the first of them wins
+        for (i, level) in levels.iter().enumerate() {
+            for later in &levels[i..] {
+                fs::write(dir(later).join("shared.txt"), "a.txt\n").unwrap();
+            }
+            assert_eq!(store().get_template_path("shared"), Some(dir(level).join("shared.txt")), "{}", level);
+            for later in &levels[i..] {
+                fs::remove_file(dir(later).join("shared.txt")).unwrap();
+            }
+        }
+
+        fs::write(dir("project").join("shared.txt"), "a.txt\n").unwrap();
+        fs::write(dir("user").join("shared.json"), "{}").unwrap();
+        let all: Vec<(String, bool)> = store().all().unwrap().into_iter().map(|t| (t.name, t.shadowed)).collect();
+        assert_eq!(all, [("shared".to_string(), false), ("shared".to_string(), true)]);
+        assert_eq!(store().installed().unwrap()[0].path, dir("project").join("shared.txt"));
+    }
+
+    #[test]
+    fn directories_listed_twice_count_once() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().to_path_buf();
+        let store = Store::ordered(std::slice::from_ref(&dir), None, Some(dir.as_os_str()), Some(dir.join(".")), Vec::new());
+        assert_eq!(store.dirs(), [(dir, Origin::Flag)]);
+    }
+}
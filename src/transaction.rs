@@ -0,0 +1,149 @@
+use crate::error::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::filesystem::FileSystem;
+
+// Everything done to the disk during one run, in order, so it can be undone.
+enum Op {
+    CreatedDir(PathBuf),
+    CreatedFile(PathBuf),
+    // an existing entry moved out of the way before being overwritten
+    MovedAside { original: PathBuf, backup: PathBuf },
+    // an existing entry renamed to a backup the user keeps
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+pub struct Journal {
+    fs: Arc<dyn FileSystem>,
+    ops: Vec<Op>,
+    // with --no-rollback there is nothing to restore, so overwritten entries are deleted outright
+    keep_backups: bool,
+}
+
+impl Journal {
+    pub fn new(fs: Arc<dyn FileSystem>, keep_backups: bool) -> Self {
+        Journal { fs, ops: Vec::new(), keep_backups }
+    }
+
+    // like std::fs::create_dir_all, but remembers every directory it had to make
+    pub fn create_dir_all(&mut self, path: &Path) -> Result<()> {
+        let mut missing = Vec::new();
+        let mut current = Some(path);
+        while let Some(dir) = current {
+            if dir.as_os_str().is_empty() || self.fs.is_dir(dir) {
+                break;
+            }
+            missing.push(dir.to_path_buf());
+            current = dir.parent();
+        }
+
+        for dir in missing.into_iter().rev() {
+            self.fs.create_dir(&dir)
+                .with_context(|| format!("Failed to create directory '{}'", dir.display()))?;
+            self.ops.push(Op::CreatedDir(dir));
+        }
+        Ok(())
+    }
+
+    pub fn create_file(&mut self, path: &Path, content: Option<&[u8]>) -> Result<()> {
+        self.fs.write(path, content.unwrap_or_default())
+            .with_context(|| format!("Failed to create file '{}'", path.display()))?;
+        self.ops.push(Op::CreatedFile(path.to_path_buf()));
+        Ok(())
+    }
+
+    // get an existing entry out of the way before it is overwritten:
+    // renamed to a hidden sibling so rollback can put it back
+    pub fn remove_existing(&mut self, path: &Path) -> Result<()> {
+        if !self.keep_backups {
+            let removed = if self.fs.is_dir(path) {
+                self.fs.remove_dir_all(path)
+            } else {
+                self.fs.remove_file(path)
+            };
+            return removed.with_context(|| format!("Failed to remove existing '{}'", path.display()));
+        }
+
+        let backup = self.backup_path(path);
+        self.fs.rename(path, &backup)
+            .with_context(|| format!("Failed to move existing '{}' aside", path.display()))?;
+        self.ops.push(Op::MovedAside { original: path.to_path_buf(), backup });
+        Ok(())
+    }
+
+    // rename an existing entry to <name>.bak (or .bak1, .bak2, ...) for good
+    pub fn backup(&mut self, path: &Path) -> Result<PathBuf> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut to = path.with_file_name(format!("{}.bak", name));
+        let mut n = 1;
+        while self.fs.exists(&to) {
+            to = path.with_file_name(format!("{}.bak{}", name, n));
+            n += 1;
+        }
+        self.fs.rename(path, &to)
+            .with_context(|| format!("Failed to back up '{}'", path.display()))?;
+        self.ops.push(Op::Renamed { from: path.to_path_buf(), to: to.clone() });
+        Ok(to)
+    }
+
+    pub fn created_count(&self) -> usize {
+        self.ops
+            .iter()
+            .filter(|op| matches!(op, Op::CreatedDir(_) | Op::CreatedFile(_)))
+            .count()
+    }
+
+    // keep everything and drop the backups of overwritten entries,
+    // returns the backups that couldn't be removed
+    pub fn commit(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for op in self.ops.drain(..) {
+            if let Op::MovedAside { backup, .. } = op {
+                let removed = if self.fs.is_dir(&backup) {
+                    self.fs.remove_dir_all(&backup)
+                } else {
+                    self.fs.remove_file(&backup)
+                };
+                if let Err(e) = removed {
+                    errors.push(format!("could not remove backup '{}': {}", backup.display(), e));
+                }
+            }
+        }
+        errors
+    }
+
+    // undo in reverse order; keeps going on errors and returns them
+    pub fn rollback(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        while let Some(op) = self.ops.pop() {
+            let result = match &op {
+                Op::CreatedFile(path) => self.fs.remove_file(path),
+                Op::CreatedDir(path) => self.fs.remove_dir(path),
+                Op::MovedAside { original, backup } => self.fs.rename(backup, original),
+                Op::Renamed { from, to } => self.fs.rename(to, from),
+            };
+            if let Err(e) = result {
+                let path = match &op {
+                    Op::CreatedFile(p) | Op::CreatedDir(p) => p,
+                    Op::MovedAside { original, .. } => original,
+                    Op::Renamed { from, .. } => from,
+                };
+                errors.push(format!("{}: {}", path.display(), e));
+            }
+        }
+        errors
+    }
+
+    fn backup_path(&self, path: &Path) -> PathBuf {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut n = 0;
+        loop {
+            let candidate = path.with_file_name(format!(".{}.treegen-bak{}", name, n));
+            if !self.fs.exists(&candidate) {
+                return candidate;
+            }
+            n += 1;
+        }
+    }
+}
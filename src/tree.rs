@@ -0,0 +1,177 @@
+use clap::ValueEnum;
+use colored::*;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::node::{self, Node, NodeKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Sort {
+    // directories before files, each sorted by name
+    DirsFirst,
+    // plain name order
+    Name,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TreeStyle {
+    pub ascii: bool,
+    pub sort: Sort,
+}
+
+struct TreeNode {
+    name: String,
+    kind: NodeKind,
+    children: Vec<TreeNode>,
+}
+
+// Nest the flat path set under `base` into a tree
+fn build(base: &Path, paths: &BTreeMap<PathBuf, Node>) -> Vec<TreeNode> {
+    let mut roots = Vec::new();
+    for (path, node) in paths {
+        let rel = match path.strip_prefix(base) {
+            Ok(p) if !p.as_os_str().is_empty() => p,
+            _ => continue,
+        };
+        let parts: Vec<String> = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        insert(&mut roots, &parts, node.kind);
+    }
+    roots
+}
+
+fn insert(level: &mut Vec<TreeNode>, parts: &[String], kind: NodeKind) {
+    let Some((first, rest)) = parts.split_first() else {
+        return;
+    };
+    let idx = match level.iter().position(|n| n.name == *first) {
+        Some(i) => i,
+        None => {
+            level.push(TreeNode { name: first.clone(), kind: NodeKind::Dir, children: Vec::new() });
+            level.len() - 1
+        }
+    };
+    if rest.is_empty() {
+        level[idx].kind = kind;
+    } else {
+        insert(&mut level[idx].children, rest, kind);
+    }
+}
+
+fn sort(level: &mut [TreeNode], how: Sort) {
+    level.sort_by(|a, b| match how {
+        Sort::DirsFirst => a.kind.cmp(&b.kind).then_with(|| a.name.cmp(&b.name)),
+        Sort::Name => a.name.cmp(&b.name),
+    });
+    for n in level {
+        sort(&mut n.children, how);
+    }
+}
+
+pub fn print_tree(base: &Path, paths: &BTreeMap<PathBuf, Node>, style: TreeStyle) {
+    if style.ascii {
+        println!("{}", "Project Structure:".bold().cyan());
+    } else {
+        println!("{}", "📦 Project Structure:".bold().cyan());
+    }
+    print!("{}", render_tree(base, paths, style));
+}
+
+// `base` followed by one line per entry below it
+pub fn render_tree(base: &Path, paths: &BTreeMap<PathBuf, Node>, style: TreeStyle) -> String {
+    let mut roots = build(base, paths);
+    sort(&mut roots, style.sort);
+
+    let mut out = format!("{}\n", base.display().to_string().blue().bold());
+    render(&roots, "", style, &mut out);
+    out
+}
+
+fn render(level: &[TreeNode], prefix: &str, style: TreeStyle, out: &mut String) {
+    for (i, n) in level.iter().enumerate() {
+        let last = i + 1 == level.len();
+        let (connector, extend) = match (style.ascii, last) {
+            (false, false) => ("├── ", "│   "),
+            (false, true) => ("└── ", "    "),
+            (true, false) => ("|-- ", "|   "),
+            (true, true) => ("`-- ", "    "),
+        };
+        out.push_str(&format!("{}{}{}\n", prefix, connector, label(n, style.ascii)));
+        render(&n.children, &format!("{}{}", prefix, extend), style, out);
+    }
+}
+
+fn label(n: &TreeNode, ascii: bool) -> String {
+    if n.kind == NodeKind::Dir {
+        let name = format!("{}/", n.name).blue().bold();
+        return if ascii { name.to_string() } else { format!("📁 {}", name) };
+    }
+
+    // dotfiles and well-known extensionless files (Makefile, LICENSE...) stand out
+    let special = n.name.starts_with('.') || node::KNOWN_FILES.contains(&n.name.as_str());
+    let name = if special { n.name.red() } else { n.name.green() };
+    if ascii {
+        return name.to_string();
+    }
+    let emoji = match Path::new(&n.name).extension().and_then(|e| e.to_str()) {
+        _ if special => "📝",
+        Some("rs") => "🦀",
+        Some("py") => "🐍",
+        Some("js") | Some("ts") => "🧩",
+        Some("toml") => "📝",
+        Some("md") => "📘",
+        Some("html") => "🌐",
+        Some("css") => "🎨",
+        _ => "📄",
+    };
+    format!("{} {}", emoji, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths() -> BTreeMap<PathBuf, Node> {
+        let nodes = [
+            Node::file("out/zeta.rs"),
+            Node::dir("out/src"),
+            Node::file("out/src/main.rs"),
+            Node::dir("out/src/bin"),
+            Node::file("out/Makefile"),
+            Node::dir("out/assets"),
+        ];
+        nodes.into_iter().map(|n| (n.path.clone(), n)).collect()
+    }
+
+    #[test]
+    fn renders_each_style() {
+        colored::control::set_override(false);
+        let base = Path::new("out");
+
+        let ascii = render_tree(base, &paths(), TreeStyle { ascii: true, sort: Sort::DirsFirst });
+        let expected = "\
+out
+|-- assets/
+|-- src/
+|   |-- bin/
+|   `-- main.rs
+|-- Makefile
+`-- zeta.rs
+";
+        assert_eq!(ascii, expected);
+
+        let unicode = render_tree(base, &paths(), TreeStyle { ascii: false, sort: Sort::Name });
+        let expected = "\
+out
+├── 📝 Makefile
+├── 📁 assets/
+├── 📁 src/
+│   ├── 📁 bin/
+│   └── 🦀 main.rs
+└── 🦀 zeta.rs
+";
+        assert_eq!(unicode, expected);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::error::{Context, Error, Result, bail};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::node::Node;
+use crate::spec::Header;
+
+pub type Vars = BTreeMap<String, String>;
+
+// clap parser for --var key=value
+pub fn parse_var(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, got '{}'", arg))?;
+    let key = key.trim();
+    if !is_var_name(key) {
+        return Err(format!("'{}' is not a valid variable name", key));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+// key=value per line, blank lines and # comments ignored
+pub fn load_vars_file(path: &Path) -> Result<Vars> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Cannot read vars file '{}'", path.display()))?;
+    let mut vars = Vars::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = parse_var(line)
+            .map_err(|e| Error::Syntax { path: path.to_path_buf(), line: i + 1, column: 1, message: e })?;
+        vars.insert(key, value.trim().to_string());
+    }
+    Ok(vars)
+}
+
+pub fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// Calls `f` with the name of every {{ name }} placeholder and returns the text
+// with each one replaced by what `f` returns (None keeps it as is).
+// Anything that isn't a plain name, like GitHub's ${{ secrets.TOKEN }}, is left alone.
+// With `escapes`, as in file contents, `\{{` stands for a literal `{{`.
+fn replace_placeholders(text: &str, escapes: bool, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        if escapes && rest[..start].ends_with('\\') {
+            out.push_str(&rest[..start - 1]);
+            out.push_str("{{");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let name = after[..end].trim();
+        out.push_str(&rest[..start]);
+        match is_var_name(name).then(|| f(name)).flatten() {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn placeholders_in(text: &str, escapes: bool, found: &mut BTreeSet<String>) {
+    replace_placeholders(text, escapes, |name| {
+        found.insert(name.to_string());
+        None
+    });
+}
+
+// the placeholders used in paths, and those used in text contents
+pub fn placeholders(groups: &[Vec<Node>]) -> (BTreeSet<String>, BTreeSet<String>) {
+    let (mut in_paths, mut in_contents) = (BTreeSet::new(), BTreeSet::new());
+    for node in groups.iter().flatten() {
+        placeholders_in(&node.path.to_string_lossy(), false, &mut in_paths);
+        if let Some(text) = node.content.as_deref().and_then(|c| std::str::from_utf8(c).ok()) {
+            placeholders_in(text, true, &mut in_contents);
+        }
+    }
+    (in_paths, in_contents)
+}
+
+pub fn substitute(text: &str, vars: &Vars) -> String {
+    replace_placeholders(text, false, |name| vars.get(name).cloned())
+}
+
+// file contents, where `\{{` is a literal `{{`
+pub fn substitute_content(text: &str, vars: &Vars) -> String {
+    replace_placeholders(text, true, |name| vars.get(name).cloned())
+}
+
+pub fn apply(groups: &mut [Vec<Node>], vars: &Vars) {
+    for node in groups.iter_mut().flatten() {
+        let path = substitute(&node.path.to_string_lossy(), vars);
+        node.path = PathBuf::from(path);
+        if let Some(content) = &node.content
+            && let Ok(text) = std::str::from_utf8(content)
+        {
+            node.content = Some(substitute_content(text, vars).into_bytes());
+        }
+    }
+}
+
+// Values for every placeholder in use: header defaults, then the vars file,
+// then --var, and finally asking for whatever is still missing.
+// Without a terminal to ask on, missing values are an error.
+// In file contents only variables the header declares are asked for, so the
+// {{ title }} of a Mustache or Vue file is kept unless it's given a value.
+pub fn resolve(
+    header: &Header,
+    groups: &[Vec<Node>],
+    file_vars: Vars,
+    cli_vars: &[(String, String)],
+    interactive: bool,
+) -> Result<Vars> {
+    let mut vars = Vars::new();
+    for (name, default) in &header.vars {
+        if let Some(default) = default {
+            vars.insert(name.clone(), default.clone());
+        }
+    }
+    vars.extend(file_vars);
+    vars.extend(cli_vars.iter().cloned());
+
+    let (in_paths, in_contents) = placeholders(groups);
+    let declared = |name: &String| header.vars.iter().any(|(n, _)| n == name);
+    let missing: Vec<String> = in_paths
+        .into_iter()
+        .chain(in_contents.into_iter().filter(declared))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|name| !vars.contains_key(name))
+        .collect();
+    if missing.is_empty() {
+        return Ok(vars);
+    }
+
+    if !interactive {
+        bail!(
+            "Unresolved template variables: {} (set them with --var name=value or --vars-file)",
+            missing.join(", ")
+        );
+    }
+    for name in missing {
+        let value = prompt(&name)?;
+        vars.insert(name, value);
+    }
+    Ok(vars)
+}
+
+fn prompt(name: &str) -> Result<String> {
+    print!("Value for '{}': ", name);
+    io::stdout().flush().with_context(|| "Cannot write to the terminal".to_string())?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .with_context(|| format!("Cannot read the value of '{}'", name))?;
+    Ok(answer.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vars {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn substitutes_known_names_only() {
+        let values = vars(&[("name", "demo")]);
+        assert_eq!(substitute("{{name}}/{{ name }}.rs", &values), "demo/demo.rs");
+        assert_eq!(substitute("{{other}} ${{ secrets.TOKEN }}", &values), "{{other}} ${{ secrets.TOKEN }}");
+    }
+
+    #[test]
+    fn escaped_braces_in_contents_stay_literal() {
+        let values = vars(&[("name", "demo")]);
+        assert_eq!(substitute_content("\\{{name}} is {{name}}", &values), "{{name}} is demo");
+        assert_eq!(substitute_content("\\\\{{name}}", &values), "\\{{name}}");
+        assert_eq!(substitute("\\{{name}}", &values), "\\demo");
+    }
+
+    #[test]
+    fn only_declared_content_variables_are_required() {
+        let groups = vec![vec![
+            Node::dir("{{project}}"),
+            Node { content: Some(b"<h1>{{ title }}</h1> by {{author}}".to_vec()), ..Node::file("{{project}}/index.vue") },
+        ]];
+        let header = Header { vars: vec![("author".to_string(), None)], ..Default::default() };
+
+        let err = resolve(&header, &groups, Vars::new(), &[], false).unwrap_err().to_string();
+        assert!(err.contains("author") && err.contains("project") && !err.contains("title"), "{}", err);
+
+        let cli = [("project".to_string(), "site".to_string()), ("author".to_string(), "me".to_string())];
+        let mut groups = groups;
+        let values = resolve(&header, &groups, Vars::new(), &cli, false).unwrap();
+        apply(&mut groups, &values);
+        assert_eq!(groups[0][1].path, PathBuf::from("site/index.vue"));
+        assert_eq!(groups[0][1].content.as_deref(), Some(&b"<h1>{{ title }}</h1> by me"[..]));
+    }
+}
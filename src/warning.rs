@@ -0,0 +1,35 @@
+use std::fmt;
+use std::path::PathBuf;
+
+// Something worth telling the user that didn't stop the work. Nothing in the
+// library prints these, they're handed back for the caller to show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    // lenient parsing made a file with entries indented under it a directory
+    MadeDirectory { path: PathBuf, line: usize, name: String },
+    // a Markdown document has several structure blocks and none was picked
+    FirstBlock { path: PathBuf, count: usize },
+    // a file that isn't UTF-8 text, saved without its content
+    NotText(PathBuf),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::MadeDirectory { path, line, name } => write!(
+                f,
+                "{}:{}: '{}' has entries indented under it, treating it as a directory.",
+                path.display(),
+                line,
+                name
+            ),
+            Warning::FirstBlock { path, count } => write!(
+                f,
+                "'{}' has {} structure blocks, using the first. Pick another with --block.",
+                path.display(),
+                count
+            ),
+            Warning::NotText(path) => write!(f, "'{}' is not text, saving it without content.", path.display()),
+        }
+    }
+}